@@ -0,0 +1,123 @@
+use minifb::{Window, WindowOptions};
+use crate::bus::Bus;
+use crate::apu::apu_device::{ApuChannel, APU_CHANNELS};
+use crate::apu::scope::SCOPE_LENGTH;
+use crate::ppu::window_common::{pack, draw_text};
+
+const LANE_HEIGHT: usize = 56;
+const TEXT_WIDTH: usize = 160;
+const FOOTER_HEIGHT: usize = 16;
+
+const DUTY_CYCLES: [&str; 4] = ["12.5", "25", "50", "75"];
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Oscilloscope and registers state for every sound channel
+pub struct ApuDebugWindow {
+    pub window: Window,
+    buffer: Vec<u32>,
+}
+
+impl ApuDebugWindow {
+    pub fn new() -> Self {
+        let width = SCOPE_LENGTH + TEXT_WIDTH;
+        let height = APU_CHANNELS.len() * LANE_HEIGHT + FOOTER_HEIGHT;
+
+        let buffer: Vec<u32> = vec![0; (width * height) as usize];
+        let options = WindowOptions {
+            resize: false,
+            ..WindowOptions::default()
+        };
+        let window = Window::new("APU", width as usize, height as usize, options).unwrap_or_else(|e| { panic!("{}", e); });
+        return Self {
+            window,
+            buffer,
+        };
+    }
+
+    pub fn update(&mut self, bus: &Bus) {
+        let width = SCOPE_LENGTH + TEXT_WIDTH;
+        let apu = bus.apu.borrow();
+
+        for pixel in self.buffer.iter_mut() {
+            *pixel = pack(0x10, 0x10, 0x10);
+        }
+
+        for channel in APU_CHANNELS.iter() {
+            let lane_y = channel.index() * LANE_HEIGHT;
+            let audible = apu.is_channel_audible(*channel);
+            let color = if audible { pack(0x40, 0xE0, 0x40) } else { pack(0x60, 0x60, 0x60) };
+
+            // Lane separator
+            for x in 0..width {
+                self.buffer[(lane_y + LANE_HEIGHT - 1) * width + x] = pack(0x40, 0x40, 0x40);
+            }
+
+            if let Some(scope) = apu.scope() {
+                let mut previous_y = None;
+                for (x, level) in scope.samples(channel.index()).enumerate() {
+                    let y = lane_y + 4 + ((1.0 - level.max(0.0).min(1.0)) * (LANE_HEIGHT - 9) as f32) as usize;
+                    // Join consecutive points with a vertical line so square waves show their edges
+                    let (from, to) = match previous_y {
+                        Some(previous_y) if previous_y < y => (previous_y, y),
+                        Some(previous_y) => (y, previous_y),
+                        None => (y, y),
+                    };
+                    for y in from..=to {
+                        self.buffer[y * width + x] = color;
+                    }
+                    previous_y = Some(y);
+                }
+            }
+
+            let info = apu.channel_debug_info(*channel);
+            let text_color = if info.enabled { pack(0xFF, 0xFF, 0xFF) } else { pack(0x80, 0x80, 0x80) };
+            let mut lines = vec![channel.name().to_string()];
+            if *channel == ApuChannel::Pulse1 || *channel == ApuChannel::Pulse2 {
+                lines[0] = format!("{} DUTY {}", channel.name(), DUTY_CYCLES[info.mode as usize]);
+            }
+            match channel {
+                ApuChannel::Pulse1 | ApuChannel::Pulse2 | ApuChannel::Triangle => {
+                    lines.push(format!("P {} {:.1}HZ", info.period, info.frequency));
+                    lines.push(format!("NOTE {}", note_name(info.frequency)));
+                    if *channel == ApuChannel::Triangle {
+                        lines.push(format!("LIN {} LEN {}", info.volume, info.length_counter));
+                    } else {
+                        let envelope = if info.constant_volume { "CST" } else { "ENV" };
+                        lines.push(format!("VOL {} {} LEN {}", info.volume, envelope, info.length_counter));
+                    }
+                }
+                ApuChannel::Noise => {
+                    lines.push(format!("P {} {:.0}HZ", info.period, info.frequency));
+                    let envelope = if info.constant_volume { "CST" } else { "ENV" };
+                    lines.push(format!("VOL {} {}", info.volume, envelope));
+                    lines.push(format!("LEN {} {}", info.length_counter, if info.mode == 1 { "SHORT" } else { "LONG" }));
+                }
+                ApuChannel::Dmc => {
+                    lines.push(format!("DAC {}", info.volume));
+                }
+                ApuChannel::Expansion => {
+                    let level = apu.scope().map(|scope| scope.latest(channel.index())).unwrap_or(0.0);
+                    lines.push(format!("OUT {:.2}", level));
+                }
+            }
+            for (i, line) in lines.iter().enumerate() {
+                draw_text(&mut self.buffer, width, SCOPE_LENGTH + 6, lane_y + 4 + i * 12, line, text_color, 2);
+            }
+        }
+
+        let footer = format!("FRAME COUNTER: {} STEP{}", apu.frame_counter_steps(), if apu.is_frame_irq_inhibited() { " IRQ OFF" } else { "" });
+        draw_text(&mut self.buffer, width, 4, APU_CHANNELS.len() * LANE_HEIGHT + 3, &footer, pack(0xFF, 0xFF, 0xFF), 2);
+
+        self.window.update_with_buffer(self.buffer.as_slice()).unwrap();
+    }
+}
+
+fn note_name(frequency: f32) -> String {
+    if frequency < 20.0 || frequency > 20_000.0 {
+        return "-".to_string();
+    }
+    let midi_note = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+    let octave = midi_note / 12 - 1;
+    return format!("{}{}", NOTE_NAMES[(midi_note % 12) as usize], octave);
+}
@@ -1,27 +1,15 @@
 use crate::bus::Bus;
-use std::sync::mpsc::SyncSender;
-use crate::apu::streaming_audio::FrameSoundBuffer;
+use crate::apu::audio_sink::{AudioSink, FrameSoundBuffer};
 use crate::apu::pulse_voice::PulseVoice;
 use crate::apu::triangle_voice::TriangleVoice;
 use crate::apu::noise_voice::NoiseVoice;
-
-// DMC registers
-bf!(DmcReg1[u8] {
-    frequency_index: 0:3,
-    unused: 4:5,
-    do_loop: 6:6,
-    irq_enable: 7:7,
-});
-bf!(DmcReg2[u8] {
-    dac: 0:6,
-    unused: 7:7,
-});
-bf!(DmcReg3[u8] {
-    sample_address: 0:7,
-});
-bf!(DmcReg4[u8] {
-    sample_length: 0:7,
-});
+use crate::apu::dpcm_voice::DpcmVoice;
+use crate::apu::resampler::Resampler;
+use crate::apu::rate_control::RateControl;
+use crate::apu::wav_sink::WavRecorder;
+use crate::apu::scope::ApuScope;
+use crate::region::Region;
+use crate::tools::wav_writer::WavSampleFormat;
 
 // Common registers
 bf!(CommonReg1[u8] {
@@ -38,17 +26,61 @@ bf!(CommonReg2[u8] {
     frame_sequencer_mode: 7:7,
 });
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ApuChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    /// Everything coming out of the cartdrige's sound chip
+    Expansion,
+}
+
+pub const APU_CHANNELS: [ApuChannel; 6] = [ApuChannel::Pulse1, ApuChannel::Pulse2, ApuChannel::Triangle, ApuChannel::Noise, ApuChannel::Dmc, ApuChannel::Expansion];
+
+impl ApuChannel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApuChannel::Pulse1 => "pulse1",
+            ApuChannel::Pulse2 => "pulse2",
+            ApuChannel::Triangle => "triangle",
+            ApuChannel::Noise => "noise",
+            ApuChannel::Dmc => "dmc",
+            ApuChannel::Expansion => "expansion",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        APU_CHANNELS.iter().find(|c| c.name() == name).cloned()
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// What the debug viewer shows about a channel
+pub struct ChannelDebugInfo {
+    pub enabled: bool,
+    /// Timer period, in CPU cycles for the noise
+    pub period: u16,
+    pub frequency: f32,
+    /// Out of the envelope, the linear counter for the triangle and the DAC for the DMC
+    pub volume: u8,
+    pub constant_volume: bool,
+    pub length_counter: u16,
+    /// Duty cycle index for the pulses, 1 for the noise's short mode
+    pub mode: u8,
+}
+
 pub struct Apu {
     square_voice1: PulseVoice,
     square_voice2: PulseVoice,
     triangle_voice: TriangleVoice,
     noise_voice: NoiseVoice,
 
-    dmc_1: DmcReg1,
-    dmc_2: DmcReg2,
-    dmc_3: DmcReg3,
-    dmc_4: DmcReg4,
-    dmc_irq: bool,
+    dmc_voice: DpcmVoice,
 
     common1: CommonReg1,
     common2: CommonReg2,
@@ -57,23 +89,35 @@ pub struct Apu {
     sequencer_divider: u32,
     sequencer_interrupt_flag: bool,
 
-    audio_output: SyncSender<FrameSoundBuffer>,
-    audio_buffer: Vec<u8>,
+    region: Region,
+
+    audio_output: Box<dyn AudioSink>,
+    audio_buffer: Vec<f32>,
+    rate_control: RateControl,
+    resampler: Resampler,
+    recorder: Option<WavRecorder>,
+    /// Soundtrack of a video recording, separate so it doesn't fight with the audio recording
+    video_recorder: Option<WavRecorder>,
+
+    master_volume: f32,
+    muted_channels: [bool; 6],
+    solo_channel: Option<ApuChannel>,
+    channel_capture: Option<[WavRecorder; 6]>,
+    channel_buffers: [Vec<f32>; 6],
+
+    scope: Option<ApuScope>,
 }
 
 impl Apu {
-    pub(crate) fn new(audio_output: SyncSender<FrameSoundBuffer>) -> Self {
+    pub(crate) fn new(audio_output: Box<dyn AudioSink>) -> Self {
+        let rate_control = RateControl::new(audio_output.sample_rate());
         Self {
             square_voice1: PulseVoice::new(false),
             square_voice2: PulseVoice::new(true),
             triangle_voice: TriangleVoice::new(),
             noise_voice: NoiseVoice::new(),
 
-            dmc_1: DmcReg1::new(0),
-            dmc_2: DmcReg2::new(0),
-            dmc_3: DmcReg3::new(0),
-            dmc_4: DmcReg4::new(0),
-            dmc_irq: false,
+            dmc_voice: DpcmVoice::new(),
 
             common1: CommonReg1::new(0),
             common2: CommonReg2::new(0),
@@ -81,11 +125,31 @@ impl Apu {
             sequencer_divider: 0,
             sequencer_interrupt_flag: false,
 
+            region: Region::Ntsc,
+
             audio_output,
-            audio_buffer: Vec::<u8>::with_capacity(14_900),
+            audio_buffer: Vec::<f32>::with_capacity(14_900),
+            rate_control,
+            resampler: Resampler::new(),
+            recorder: None,
+            video_recorder: None,
+
+            master_volume: 1.0,
+            muted_channels: [false; 6],
+            solo_channel: None,
+            channel_capture: None,
+            channel_buffers: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+
+            scope: None,
         }
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.noise_voice.set_region(region);
+        self.dmc_voice.set_region(region);
+    }
+
     pub fn cpu_write(&mut self, bus: &Bus, address: u16, data: u8) {
         if address >= 0x4000 && address <= 0x4003 {
             self.square_voice1.write_register(((address & 0x03) as u8), data);
@@ -95,14 +159,11 @@ impl Apu {
             self.triangle_voice.write_register(((address & 0x03) as u8), data);
         } else if address >= 0x400C && address <= 0x400F {
             self.noise_voice.write_register(((address & 0x03) as u8), data);
+        } else if address >= 0x4010 && address <= 0x4013 {
+            self.dmc_voice.write_register(((address & 0x03) as u8), data);
         }
 
         match address {
-            // DPCM
-            0x4010 => { self.dmc_1.val = data; }
-            0x4011 => { self.dmc_2.val = data; }
-            0x4012 => { self.dmc_3.val = data; }
-            0x4013 => { self.dmc_4.val = data; }
             // Control
             0x4015 => {
                 self.common1.val = data;
@@ -111,11 +172,13 @@ impl Apu {
                 self.triangle_voice.control_enabled = self.common1.length_ctr_enable_triangle() != 0;
                 self.noise_voice.control_enabled = self.common1.length_ctr_enable_noise() != 0;
 
-                self.dmc_irq = false;
-                //TODO DMC behavior
-                //If d is set and the DMC's DMA reader has no more sample bytes to fetch, the DMC
-                //sample is restarted. If d is clear then the DMA reader's sample bytes remaining
-                //is set to 0.
+                // Disabling a channel clears its length counter right away, not on the next half frame
+                if !self.square_voice1.control_enabled { self.square_voice1.length_counter = 0; }
+                if !self.square_voice2.control_enabled { self.square_voice2.length_counter = 0; }
+                if !self.triangle_voice.control_enabled { self.triangle_voice.length_counter = 0; }
+                if !self.noise_voice.control_enabled { self.noise_voice.length_counter = 0; }
+
+                self.dmc_voice.set_enabled(self.common1.length_ctr_enable_dmc() != 0);
             }
             0x4017 => {
                 self.common2.val = data;
@@ -132,12 +195,14 @@ impl Apu {
 
     pub fn cpu_read(&mut self, address: u16, data: &mut u8) {
         if address == 0x4015 {
-            *data = ((self.dmc_irq as u8) << 7) |
+            // Bit 5 is open bus
+            *data = (*data & 0x20) |
+                ((self.dmc_voice.irq_flag as u8) << 7) |
                 ((self.sequencer_interrupt_flag as u8) << 6) |
-                /*(((self.dmc_sample_bytes_remaining > 0) as u8) << 4) | */
+                (((self.dmc_voice.bytes_remaining() > 0) as u8) << 4) |
                 (((self.noise_voice.length_counter > 0) as u8) << 3) |
                 (((self.triangle_voice.length_counter > 0) as u8) << 2) |
-                (((self.square_voice2.length_counter > 0) as u8) << 1);
+                (((self.square_voice2.length_counter > 0) as u8) << 1) |
                 (((self.square_voice1.length_counter > 0) as u8) << 0);
 
             self.sequencer_interrupt_flag = false;
@@ -146,8 +211,9 @@ impl Apu {
 
     pub fn clock_main(&mut self, cycles: u32) {
         self.sequencer_divider += cycles;
-        if self.sequencer_divider >= 89490 {
-            self.sequencer_divider -= 89490;
+        let period = self.region.apu_frame_period();
+        if self.sequencer_divider >= period {
+            self.sequencer_divider -= period;
             self.clock_sequencer();
         }
     }
@@ -180,8 +246,17 @@ impl Apu {
         }
     }
 
+    /// Address the DMC wants fetched, the bus steals CPU cycles to do it
+    pub fn dmc_dma_request(&mut self) -> Option<u16> {
+        self.dmc_voice.dma_request()
+    }
+
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc_voice.dma_complete(data);
+    }
+
     pub fn is_raising_interrupt(&self) -> bool {
-        self.common2.irq_disable() == 0 && self.sequencer_interrupt_flag
+        (self.common2.irq_disable() == 0 && self.sequencer_interrupt_flag) || self.dmc_voice.is_raising_interrupt()
     }
 
     fn clock_length_counters_and_sweep_units(&mut self) {
@@ -198,50 +273,257 @@ impl Apu {
         self.noise_voice.clock_envelope();
     }
 
-    pub fn clock_cpu_clock(&mut self) {
+    pub fn clock_cpu_clock(&mut self, expansion_audio: f32) {
         self.square_voice1.clock_cpu();
         self.square_voice2.clock_cpu();
         self.triangle_voice.clock_cpu();
         self.noise_voice.clock_cpu();
+        self.dmc_voice.clock_cpu();
+
+        let outputs = [self.square_voice1.output(), self.square_voice2.output(), self.triangle_voice.output(), self.noise_voice.output(), self.dmc_voice.output()];
+
+        if self.channel_capture.is_some() {
+            // Each voice as it would sound on its own, regardless of muting
+            self.channel_buffers[0].push(mix(outputs[0], 0, 0, 0, 0));
+            self.channel_buffers[1].push(mix(0, outputs[1], 0, 0, 0));
+            self.channel_buffers[2].push(mix(0, 0, outputs[2], 0, 0));
+            self.channel_buffers[3].push(mix(0, 0, 0, outputs[3], 0));
+            self.channel_buffers[4].push(mix(0, 0, 0, 0, outputs[4]));
+            self.channel_buffers[5].push(expansion_audio);
+        }
 
-        let output = self.square_voice1.output() + self.square_voice2.output() + self.triangle_voice.output() + self.noise_voice.output();
-        //let output = self.noise_voice.output();
-        self.audio_buffer.push(output);
+        if let Some(scope) = self.scope.as_mut() {
+            // Expansion chips are quite a bit quieter than a full scale 2A03 voice
+            let expansion_level = if expansion_audio > 0.25 { 1.0 } else { expansion_audio * 4.0 };
+            scope.push(&[
+                outputs[0] as f32 / 15.0,
+                outputs[1] as f32 / 15.0,
+                outputs[2] as f32 / 15.0,
+                outputs[3] as f32 / 15.0,
+                outputs[4] as f32 / 127.0,
+                expansion_level,
+            ]);
+        }
+
+        let mut audible = [0u8; 5];
+        for channel in APU_CHANNELS[0..5].iter() {
+            if self.is_channel_audible(*channel) {
+                audible[channel.index()] = outputs[channel.index()];
+            }
+        }
+        let expansion_audio = if self.is_channel_audible(ApuChannel::Expansion) { expansion_audio } else { 0.0 };
+
+        let output = mix(audible[0], audible[1], audible[2], audible[3], audible[4]) + expansion_audio;
+        self.audio_buffer.push(output * self.master_volume);
+    }
+
+    /// The scope costs a little on every cycle, so it's only kept while someone looks at it
+    pub fn set_scope_enabled(&mut self, enabled: bool) {
+        self.scope = if enabled { Some(ApuScope::new()) } else { None };
+    }
+
+    pub fn scope(&self) -> Option<&ApuScope> {
+        self.scope.as_ref()
+    }
+
+    pub fn channel_debug_info(&self, channel: ApuChannel) -> ChannelDebugInfo {
+        match channel {
+            ApuChannel::Pulse1 | ApuChannel::Pulse2 => {
+                let voice = if channel == ApuChannel::Pulse1 { &self.square_voice1 } else { &self.square_voice2 };
+                ChannelDebugInfo {
+                    enabled: voice.control_enabled,
+                    period: voice.period(),
+                    frequency: (self.region.cpu_clock_rate() / (16.0 * (voice.period() as f64 + 1.0))) as f32,
+                    volume: voice.volume(),
+                    constant_volume: voice.is_constant_volume(),
+                    length_counter: voice.length_counter,
+                    mode: voice.duty(),
+                }
+            }
+            ApuChannel::Triangle => ChannelDebugInfo {
+                enabled: self.triangle_voice.control_enabled,
+                period: self.triangle_voice.period(),
+                frequency: (self.region.cpu_clock_rate() / (32.0 * (self.triangle_voice.period() as f64 + 1.0))) as f32,
+                volume: self.triangle_voice.linear_counter(),
+                constant_volume: false,
+                length_counter: self.triangle_voice.length_counter,
+                mode: 0,
+            },
+            ApuChannel::Noise => ChannelDebugInfo {
+                enabled: self.noise_voice.control_enabled,
+                period: self.noise_voice.period(),
+                frequency: (self.region.cpu_clock_rate() / self.noise_voice.period() as f64) as f32,
+                volume: self.noise_voice.volume(),
+                constant_volume: self.noise_voice.is_constant_volume(),
+                length_counter: self.noise_voice.length_counter,
+                mode: self.noise_voice.is_short_mode() as u8,
+            },
+            ApuChannel::Dmc => ChannelDebugInfo {
+                enabled: self.dmc_voice.bytes_remaining() > 0,
+                period: self.dmc_voice.period(),
+                frequency: (self.region.cpu_clock_rate() / self.dmc_voice.period() as f64) as f32,
+                volume: self.dmc_voice.output(),
+                constant_volume: true,
+                length_counter: self.dmc_voice.bytes_remaining(),
+                mode: 0,
+            },
+            ApuChannel::Expansion => ChannelDebugInfo {
+                enabled: true,
+                period: 0,
+                frequency: 0.0,
+                volume: 0,
+                constant_volume: true,
+                length_counter: 0,
+                mode: 0,
+            },
+        }
+    }
+
+    /// 4 or 5 steps
+    pub fn frame_counter_steps(&self) -> u8 {
+        if self.common2.frame_sequencer_mode() == 0 { 4 } else { 5 }
+    }
 
-        //TODO other channels
+    pub fn is_frame_irq_inhibited(&self) -> bool {
+        self.common2.irq_disable() != 0
+    }
+
+    pub fn set_triangle_ultrasonic_suppression(&mut self, suppress: bool) {
+        self.triangle_voice.suppress_ultrasonic = suppress;
+    }
+
+    /// Scales the final mix, used for fading out
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume;
+    }
+
+    pub fn set_channel_muted(&mut self, channel: ApuChannel, muted: bool) {
+        self.muted_channels[channel.index()] = muted;
+    }
+
+    pub fn is_channel_muted(&self, channel: ApuChannel) -> bool {
+        self.muted_channels[channel.index()]
+    }
+
+    /// Only this channel will be heard while set, mutes are left untouched
+    pub fn set_solo_channel(&mut self, channel: Option<ApuChannel>) {
+        self.solo_channel = channel;
+    }
+
+    pub fn solo_channel(&self) -> Option<ApuChannel> {
+        self.solo_channel
+    }
+
+    pub fn is_channel_audible(&self, channel: ApuChannel) -> bool {
+        match self.solo_channel {
+            Some(solo) => solo == channel,
+            None => !self.muted_channels[channel.index()],
+        }
+    }
+
+    /// Writes every channel's own output to `<prefix>_<channel>.wav`
+    pub fn start_channel_capture(&mut self, prefix: &str, sample_rate: u32, format: WavSampleFormat) {
+        println!("Capturing channels to {}_*.wav", prefix);
+        let recorder = |channel: ApuChannel| WavRecorder::new(&format!("{}_{}.wav", prefix, channel.name()), sample_rate, 1, format);
+        self.channel_capture = Some([
+            recorder(ApuChannel::Pulse1),
+            recorder(ApuChannel::Pulse2),
+            recorder(ApuChannel::Triangle),
+            recorder(ApuChannel::Noise),
+            recorder(ApuChannel::Dmc),
+            recorder(ApuChannel::Expansion),
+        ]);
+    }
+
+    pub fn stop_channel_capture(&mut self) {
+        if self.channel_capture.is_some() {
+            println!("Channel capture stopped");
+        }
+        self.channel_capture = None;
+        self.channel_buffers.iter_mut().for_each(|b| b.clear());
+    }
+
+    pub fn is_capturing_channels(&self) -> bool {
+        self.channel_capture.is_some()
     }
 
     pub fn frame_done(&mut self) {
-        let mut swap = Vec::<u8>::with_capacity(14_900);
+        let swap = Vec::<f32>::with_capacity(14_900);
         let downsample_me = std::mem::replace(&mut self.audio_buffer, swap);
 
-        let sample_length = downsample_me.len();
+        let ratio = match self.audio_output.queued_samples() {
+            Some(queued_samples) => self.rate_control.ratio(queued_samples),
+            None => 1.0,
+        };
+        let cpu_clock_rate = self.region.cpu_clock_rate();
+        let step = cpu_clock_rate / (self.audio_output.sample_rate() as f64 * ratio);
 
-        let mut bytes_req = 800;
-        let mut fvec = Vec::<u8>::new();
-        for i in 0..bytes_req {
-            let next = i + 1;
-            let mut start = (((sample_length as f32 / bytes_req as f32) * (i as f32)) as usize);
-            let mut end = (((sample_length as f32 / bytes_req as f32) * (next as f32)) as usize);
-            if start > sample_length {
-                start = sample_length - 1;
-            }
-            if end > sample_length {
-                end = sample_length - 1;
-            }
-            let size = end - start;
-            assert_ne!(size, 0);
-            let mut acc = 0.0f32;
-            for sample_index in start..end {
-                acc += downsample_me[sample_index] as f32;
+        let mut resampled = Vec::<f32>::with_capacity(1024);
+        self.resampler.resample(downsample_me.iter().cloned(), step, &mut resampled);
+
+        let frame: FrameSoundBuffer = resampled.iter().map(|s| [*s, *s]).collect();
+        self.audio_output.push_frame(frame);
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&downsample_me, cpu_clock_rate);
+        }
+        if let Some(recorder) = self.video_recorder.as_mut() {
+            recorder.record(&downsample_me, cpu_clock_rate);
+        }
+
+        if let Some(channel_recorders) = self.channel_capture.as_mut() {
+            for (recorder, buffer) in channel_recorders.iter_mut().zip(self.channel_buffers.iter_mut()) {
+                recorder.record(buffer, cpu_clock_rate);
+                buffer.clear();
             }
-            acc /= size as f32;
+        }
+    }
 
-            fvec.push(acc as u8);
+    pub fn start_recording(&mut self, filename: &str, sample_rate: u32, format: WavSampleFormat) {
+        println!("Recording audio to {}", filename);
+        self.recorder = Some(WavRecorder::new(filename, sample_rate, 2, format));
+    }
+
+    pub fn stop_recording(&mut self) {
+        if self.recorder.is_some() {
+            println!("Audio recording stopped");
         }
+        // Dropping the recorder finalizes the file
+        self.recorder = None;
+    }
 
-        self.audio_output.send(fvec);
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
     }
+
+    pub fn start_video_audio(&mut self, filename: &str, sample_rate: u32, format: WavSampleFormat) {
+        self.video_recorder = Some(WavRecorder::new(filename, sample_rate, 2, format));
+    }
+
+    pub fn stop_video_audio(&mut self) {
+        self.video_recorder = None;
+    }
+
+    /// True when more audio is queued than the rate control aims for, used to pace the
+    /// emulation on the sound card clock when audio is the master clock
+    pub fn is_audio_ahead(&self) -> bool {
+        match self.audio_output.queued_samples() {
+            Some(queued_samples) => queued_samples > self.rate_control.target_queued_samples(),
+            None => false,
+        }
+    }
+}
+
+/// Non-linear mixer, straight from the hardware's resistor network.
+/// Output is in the 0..1 range.
+fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse_sum = (pulse1 + pulse2) as f32;
+    let pulse_out = if pulse_sum == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse_sum + 100.0) };
+
+    let tnd_sum = triangle as f32 / 8227.0 + noise as f32 / 12241.0 + dmc as f32 / 22638.0;
+    let tnd_out = if tnd_sum == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd_sum + 100.0) };
+
+    pulse_out + tnd_out
 }
 
 pub const LENGTH_COUNTER_LOOKUP_TABLE: [[u8; 2]; 16] = [
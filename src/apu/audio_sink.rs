@@ -0,0 +1,40 @@
+/// One output sample, left then right
+pub type StereoSample = [f32; 2];
+
+/// Mixed APU output for one video frame, at the sink's sample rate
+pub type FrameSoundBuffer = Vec<StereoSample>;
+
+/// Where the APU sends its mixed output, once per video frame
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    /// Samples pushed but not played yet, `None` for sinks that don't play in realtime
+    fn queued_samples(&self) -> Option<usize>;
+
+    fn push_frame(&mut self, frame: FrameSoundBuffer);
+}
+
+/// Throws everything away, for headless runs that don't care about sound
+pub struct NullAudioSink {
+    sample_rate: u32,
+}
+
+impl NullAudioSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+        }
+    }
+}
+
+impl AudioSink for NullAudioSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queued_samples(&self) -> Option<usize> {
+        None
+    }
+
+    fn push_frame(&mut self, _frame: FrameSoundBuffer) {}
+}
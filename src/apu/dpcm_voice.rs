@@ -1,3 +1,164 @@
-pub struct DcmVoice {
+use crate::region::Region;
 
-}
\ No newline at end of file
+// DMC registers
+bf!(DmcReg1[u8] {
+    frequency_index: 0:3,
+    unused: 4:5,
+    do_loop: 6:6,
+    irq_enable: 7:7,
+});
+
+/// Delta modulation channel: plays 1-bit delta encoded samples fetched from CPU memory.
+/// The fetches are done by the bus, which stalls the CPU for them.
+pub struct DpcmVoice {
+    register1: DmcReg1,
+    sample_address: u8,
+    sample_length: u8,
+
+    pub irq_flag: bool,
+
+    // Memory reader
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    dma_requested: bool,
+
+    // Output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+
+    timer: u16,
+    timer_periods: &'static [u16; 16],
+}
+
+impl DpcmVoice {
+    pub fn new() -> Self {
+        Self {
+            register1: DmcReg1::new(0),
+            sample_address: 0,
+            sample_length: 0,
+
+            irq_flag: false,
+
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            dma_requested: false,
+
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+
+            timer: 0,
+            timer_periods: Region::Ntsc.dmc_periods(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.timer_periods = region.dmc_periods();
+    }
+
+    pub fn write_register(&mut self, address: u8, data: u8) {
+        match address {
+            /* 0x4010 */ 0x00 => {
+                self.register1.val = data;
+                if self.register1.irq_enable() == 0 {
+                    self.irq_flag = false;
+                }
+            }
+            /* 0x4011 */ 0x01 => { self.output_level = data & 0x7F; }
+            /* 0x4012 */ 0x02 => { self.sample_address = data; }
+            /* 0x4013 */ 0x03 => { self.sample_length = data; }
+            _ => {}
+        }
+    }
+
+    /// Bit 4 of $4015
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart_sample();
+        }
+        self.irq_flag = false;
+    }
+
+    pub fn bytes_remaining(&self) -> u16 {
+        self.bytes_remaining
+    }
+
+    pub fn period(&self) -> u16 {
+        self.timer_periods[self.register1.frequency_index() as usize]
+    }
+
+    pub fn is_raising_interrupt(&self) -> bool {
+        self.irq_flag
+    }
+
+    fn restart_sample(&mut self) {
+        self.current_address = 0xC000 | ((self.sample_address as u16) << 6);
+        self.bytes_remaining = ((self.sample_length as u16) << 4) + 1;
+    }
+
+    /// Address the bus should fetch for us, once per empty sample buffer
+    pub fn dma_request(&mut self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 && !self.dma_requested {
+            self.dma_requested = true;
+            return Some(self.current_address);
+        }
+        return None;
+    }
+
+    pub fn dma_complete(&mut self, data: u8) {
+        self.dma_requested = false;
+        self.sample_buffer = Some(data);
+
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.register1.do_loop() == 1 {
+                self.restart_sample();
+            } else if self.register1.irq_enable() == 1 {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period() - 1;
+
+        if !self.silence {
+            if self.shift_register & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => { self.silence = true; }
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
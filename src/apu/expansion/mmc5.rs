@@ -0,0 +1,85 @@
+use crate::apu::expansion::{ExpansionAudio, APU_PULSE_STEP_LEVEL};
+use crate::apu::pulse_voice::PulseVoice;
+
+// Raw PCM full scale is roughly on par with the DMC's
+const MMC5_PCM_STEP_LEVEL: f32 = 0.00225;
+
+// The MMC5 has its own ~240Hz frame counter that clocks both envelopes and length counters
+const MMC5_FRAME_PERIOD: u32 = 7457;
+
+/// Nintendo MMC5 sound: two 2A03 pulses minus the sweep units, and a raw 8-bit PCM channel
+pub struct Mmc5Audio {
+    pulse1: PulseVoice,
+    pulse2: PulseVoice,
+    pcm: u8,
+    pcm_read_mode: bool,
+
+    frame_divider: u32,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: PulseVoice::new_without_sweep_unit(),
+            pulse2: PulseVoice::new_without_sweep_unit(),
+            pcm: 0,
+            pcm_read_mode: false,
+            frame_divider: 0,
+        }
+    }
+}
+
+impl ExpansionAudio for Mmc5Audio {
+    fn write_register(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x5000..=0x5003 => { self.pulse1.write_register((address & 0x03) as u8, data); }
+            0x5004..=0x5007 => { self.pulse2.write_register((address & 0x03) as u8, data); }
+            0x5010 => { self.pcm_read_mode = (data & 0x01) != 0; }
+            0x5011 => {
+                // Writing 0 is ignored, and so are writes while in read mode
+                if !self.pcm_read_mode && data != 0 {
+                    self.pcm = data;
+                }
+            }
+            0x5015 => {
+                self.pulse1.control_enabled = (data & 0x01) != 0;
+                self.pulse2.control_enabled = (data & 0x02) != 0;
+                if !self.pulse1.control_enabled {
+                    self.pulse1.length_counter = 0;
+                }
+                if !self.pulse2.control_enabled {
+                    self.pulse2.length_counter = 0;
+                }
+            }
+            _ => { return false; }
+        }
+        return true;
+    }
+
+    // $5015 reads back the length counters status
+    fn read_register(&mut self, address: u16, data: &mut u8) -> bool {
+        if address == 0x5015 {
+            *data = (((self.pulse2.length_counter > 0) as u8) << 1) | ((self.pulse1.length_counter > 0) as u8);
+            return true;
+        }
+        return false;
+    }
+
+    fn clock_cpu(&mut self) {
+        self.pulse1.clock_cpu();
+        self.pulse2.clock_cpu();
+
+        self.frame_divider += 1;
+        if self.frame_divider >= MMC5_FRAME_PERIOD {
+            self.frame_divider = 0;
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.pulse1.clock_length_counter_and_sweep_unit();
+            self.pulse2.clock_length_counter_and_sweep_unit();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        (self.pulse1.output() + self.pulse2.output()) as f32 * APU_PULSE_STEP_LEVEL + self.pcm as f32 * MMC5_PCM_STEP_LEVEL
+    }
+}
@@ -0,0 +1,58 @@
+pub mod vrc6;
+pub mod mmc5;
+
+// Expansion chips bits, as found in the NSF header
+pub const NSF_CHIP_VRC6: u8 = 0x01;
+pub const NSF_CHIP_VRC7: u8 = 0x02;
+pub const NSF_CHIP_FDS: u8 = 0x04;
+pub const NSF_CHIP_MMC5: u8 = 0x08;
+pub const NSF_CHIP_NAMCO163: u8 = 0x10;
+pub const NSF_CHIP_SUNSOFT5B: u8 = 0x20;
+
+// Output of one volume step on the 2A03 pulses, for small volumes the non-linear mixer
+// is close enough to linear. Expansion chips are scaled relatively to it.
+pub const APU_PULSE_STEP_LEVEL: f32 = 0.00752;
+
+/// Sound chip living on the cartdrige. The cartdrige forwards the register writes and clocks
+/// it along the CPU, the APU mixes its output with its own voices.
+pub trait ExpansionAudio {
+    /// Returns true if the address belongs to the chip
+    fn write_register(&mut self, address: u16, data: u8) -> bool;
+
+    /// Most chips are write-only
+    fn read_register(&mut self, _address: u16, _data: &mut u8) -> bool {
+        false
+    }
+
+    fn clock_cpu(&mut self);
+
+    /// Contribution to the final mix, on the same scale as the APU's own mixer
+    fn output(&self) -> f32;
+}
+
+/// Some NSF rips use several chips at once
+pub struct ExpansionAudioChips {
+    pub chips: Vec<Box<dyn ExpansionAudio>>,
+}
+
+impl ExpansionAudio for ExpansionAudioChips {
+    fn write_register(&mut self, address: u16, data: u8) -> bool {
+        let mut handled = false;
+        for chip in self.chips.iter_mut() {
+            handled |= chip.write_register(address, data);
+        }
+        handled
+    }
+
+    fn read_register(&mut self, address: u16, data: &mut u8) -> bool {
+        self.chips.iter_mut().any(|chip| chip.read_register(address, data))
+    }
+
+    fn clock_cpu(&mut self) {
+        self.chips.iter_mut().for_each(|chip| chip.clock_cpu());
+    }
+
+    fn output(&self) -> f32 {
+        self.chips.iter().map(|chip| chip.output()).sum()
+    }
+}
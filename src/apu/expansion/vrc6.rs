@@ -0,0 +1,181 @@
+use crate::apu::expansion::{ExpansionAudio, APU_PULSE_STEP_LEVEL};
+
+// VRC6 pulses are about as loud as the 2A03 ones
+const VRC6_STEP_LEVEL: f32 = APU_PULSE_STEP_LEVEL;
+
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+
+    timer: u16,
+    duty_step: u8,
+}
+
+impl Vrc6Pulse {
+    fn new() -> Self {
+        Self {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            duty_step: 15,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = (data & 0x80) != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => { self.period = (self.period & 0x0F00) | data as u16; }
+            2 => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    self.duty_step = 15;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, period_shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> period_shift;
+            self.duty_step = if self.duty_step == 0 { 15 } else { self.duty_step - 1 };
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.duty_step <= self.duty) { self.volume } else { 0 }
+    }
+}
+
+struct Vrc6Saw {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn new() -> Self {
+        Self {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => { self.rate = data & 0x3F; }
+            1 => { self.period = (self.period & 0x0F00) | data as u16; }
+            2 => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, period_shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> period_shift;
+
+            // The accumulator is bumped every other step, and cleared on the 14th
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step % 2 == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Konami VRC6: two pulses with 8 duty settings and a sawtooth.
+/// Expects mapper 24 addressing, mapper 26 swaps A0 and A1 before forwarding.
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    saw: Vrc6Saw,
+
+    halt: bool,
+    period_shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Vrc6Pulse::new(),
+            pulse2: Vrc6Pulse::new(),
+            saw: Vrc6Saw::new(),
+            halt: false,
+            period_shift: 0,
+        }
+    }
+}
+
+impl ExpansionAudio for Vrc6Audio {
+    fn write_register(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x9003 => {
+                self.halt = (data & 0x01) != 0;
+                self.period_shift = if (data & 0x04) != 0 { 8 } else if (data & 0x02) != 0 { 4 } else { 0 };
+            }
+            0x9000..=0x9002 => { self.pulse1.write_register(address & 0x03, data); }
+            0xA000..=0xA002 => { self.pulse2.write_register(address & 0x03, data); }
+            0xB000..=0xB002 => { self.saw.write_register(address & 0x03, data); }
+            _ => { return false; }
+        }
+        return true;
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse1.clock(self.period_shift);
+        self.pulse2.clock(self.period_shift);
+        self.saw.clock(self.period_shift);
+    }
+
+    fn output(&self) -> f32 {
+        (self.pulse1.output() + self.pulse2.output() + self.saw.output()) as f32 * VRC6_STEP_LEVEL
+    }
+}
@@ -4,4 +4,11 @@ mod pulse_voice;
 mod triangle_voice;
 mod noise_voice;
 mod dpcm_voice;
-pub mod apu_device;
\ No newline at end of file
+mod resampler;
+mod rate_control;
+pub mod scope;
+pub mod apu_debug_viewer;
+pub mod audio_sink;
+pub mod wav_sink;
+pub mod apu_device;
+pub mod expansion;
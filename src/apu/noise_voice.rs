@@ -1,4 +1,5 @@
 use crate::apu::apu_device::LENGTH_COUNTER_LOOKUP_TABLE;
+use crate::region::Region;
 // Noise voice registers
 bf!(NoiseVoiceReg1[u8] {
     volume: 0:3,
@@ -34,6 +35,7 @@ pub struct NoiseVoice {
     shift_register: u16,
 
     timer: u16,
+    timer_periods: &'static [u16; 16],
 }
 
 impl NoiseVoice {
@@ -54,9 +56,14 @@ impl NoiseVoice {
             shift_register: 1,
 
             timer: 0,
+            timer_periods: Region::Ntsc.noise_periods(),
         }
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.timer_periods = region.noise_periods();
+    }
+
     pub fn write_register(&mut self, address: u8, data: u8) {
         match address {
             /* 0x400C */ 0x00 => { self.register1.val = data; }
@@ -107,7 +114,7 @@ impl NoiseVoice {
     }
 
     pub fn clock_cpu(&mut self) {
-        let timer_period = TIMER_PERIODS[self.register3.period_index() as usize];
+        let timer_period = self.timer_periods[self.register3.period_index() as usize];
         if self.timer == 0 {
             let bit14 = if self.register3.mode() == 1 {
                 let bit0 = self.shift_register & 0x01;
@@ -126,27 +133,25 @@ impl NoiseVoice {
         self.timer -= 1;
     }
 
+    /// In CPU cycles
+    pub fn period(&self) -> u16 {
+        self.timer_periods[self.register3.period_index() as usize]
+    }
+
+    pub fn is_short_mode(&self) -> bool {
+        self.register3.mode() == 1
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume_out_of_envelope
+    }
+
+    pub fn is_constant_volume(&self) -> bool {
+        self.register1.envelope_disable() == 1
+    }
+
     pub fn output(&self) -> u8 {
         let bit0 = (self.shift_register & 0x01) as u8;
         self.volume_out_of_envelope * bit0 * ((self.length_counter > 0) as u8)
     }
 }
-
-const TIMER_PERIODS: [u16; 16] = [
-    0x04,
-    0x08,
-    0x10,
-    0x20,
-    0x40,
-    0x60,
-    0x80,
-    0xA0,
-    0xCA,
-    0xFE,
-    0x17C,
-    0x1FC,
-    0x2FA,
-    0x3F8,
-    0x7F2,
-    0xFE4,
-];
\ No newline at end of file
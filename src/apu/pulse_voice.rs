@@ -23,6 +23,8 @@ bf!(SquareVoiceReg4[u8] {
 
 pub struct PulseVoice {
     is_second_pulse_voice: bool,
+    // MMC5 pulses are the same minus the sweep unit
+    has_sweep_unit: bool,
 
     pub control_enabled: bool,
     pub length_counter: u16,
@@ -39,7 +41,6 @@ pub struct PulseVoice {
 
     sweep_divider: u8,
     sweep_freshly_reset_flag: bool,
-    sweep_output: bool,
 
     timer: u16,
     cpu_clock_divider: u8,
@@ -51,6 +52,7 @@ impl PulseVoice {
     pub fn new(is_second_pulse_voice: bool) -> Self {
         Self {
             is_second_pulse_voice,
+            has_sweep_unit: true,
 
             control_enabled: false,
             length_counter: 0,
@@ -67,7 +69,6 @@ impl PulseVoice {
 
             sweep_divider: 0,
             sweep_freshly_reset_flag: false,
-            sweep_output: false,
 
             timer: 0,
             cpu_clock_divider: 0,
@@ -76,6 +77,13 @@ impl PulseVoice {
         }
     }
 
+    pub fn new_without_sweep_unit() -> Self {
+        Self {
+            has_sweep_unit: false,
+            ..Self::new(false)
+        }
+    }
+
     pub fn write_register(&mut self, address: u8, data: u8) {
         match address {
             0x00 => { self.register1.val = data; }
@@ -107,39 +115,47 @@ impl PulseVoice {
             self.length_counter = 0;
         }
 
-        // Sweep unit
-        let voice_period = (self.register3 as u16) | ((self.register4.period_high() as u16) << 8);
-        let mut shifter_result = voice_period >> self.register2.shift() as u16;
-        if self.register2.negative() == 1 {
-            shifter_result = !shifter_result;
+        if !self.has_sweep_unit {
+            return;
         }
-        if self.is_second_pulse_voice {
-            shifter_result = shifter_result.wrapping_add(1);
+
+        // Sweep unit: the period only gets updated when the divider hits zero, and never
+        // while the channel is muted by the sweep
+        if self.sweep_divider == 0 && self.register2.enable_sweep() == 1 && self.register2.shift() != 0 && !self.is_muted_by_sweep() {
+            let target_period = self.sweep_target_period() as u16;
+            self.register3 = (target_period & 0xFF) as u8;
+            self.register4.set_period_high(((target_period >> 8) & 0x07) as u8);
         }
-        shifter_result = shifter_result.wrapping_add(voice_period);
 
-        let sweep_divider_period = self.register2.period() + 1;
-        if self.sweep_divider > 0 {
+        if self.sweep_divider == 0 || self.sweep_freshly_reset_flag {
+            self.sweep_divider = self.register2.period();
+            self.sweep_freshly_reset_flag = false;
         } else {
-            let mut dac_output = true;
-            if voice_period < 8 || shifter_result > 0x7FF {
-                dac_output = false;
-            } else if self.register2.enable_sweep() == 1 && self.register2.shift() != 0 {
-                self.register3 = ((shifter_result & 0xFF) as u8);
-                self.register4.set_period_high(((shifter_result >> 8) & 0xFF) as u8);
-            }
-            self.sweep_output = dac_output;
-
-            self.sweep_divider = sweep_divider_period;
+            self.sweep_divider -= 1;
         }
-        self.sweep_divider -= 1;
+    }
 
-        if self.sweep_freshly_reset_flag {
-            self.sweep_freshly_reset_flag = false;
-            self.sweep_divider = sweep_divider_period;
+    // The sweep adder runs all the time, whether the sweep is enabled or not.
+    // Pulse 1 negates with ones' complement and pulse 2 with two's complement,
+    // so with the same settings pulse 1 ends up one lower.
+    fn sweep_target_period(&self) -> i32 {
+        let period = self.period() as i32;
+        let change = period >> self.register2.shift();
+        if self.register2.negative() == 1 {
+            if self.is_second_pulse_voice {
+                period - change
+            } else {
+                period - change - 1
+            }
+        } else {
+            period + change
         }
     }
 
+    fn is_muted_by_sweep(&self) -> bool {
+        self.has_sweep_unit && (self.period() < 8 || self.sweep_target_period() > 0x7FF)
+    }
+
     pub fn clock_envelope(&mut self) {
         let divider_period = self.register1.envelope_period() + 1;
         if self.envelope_freshly_reset_flag {
@@ -176,10 +192,27 @@ impl PulseVoice {
         self.timer -= 1;
     }
 
+    pub fn period(&self) -> u16 {
+        (self.register3 as u16) | ((self.register4.period_high() as u16) << 8)
+    }
+
+    pub fn duty(&self) -> u8 {
+        self.register1.duty()
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume_out_of_envelope
+    }
+
+    pub fn is_constant_volume(&self) -> bool {
+        self.register1.envelope_disable() == 1
+    }
+
     pub fn output(&self) -> u8 {
         let sequence = self.output_sequencer;
         let waveform = ((((SQUARE_WAVEFORM_SEQUENCES[self.register1.duty() as usize] >> sequence) & 0x01) != 0) as u8) * 1;
-        self.volume_out_of_envelope * (self.sweep_output as u8) * waveform* ((self.length_counter > 0) as u8)
+        let sweep_output = !self.is_muted_by_sweep();
+        self.volume_out_of_envelope * (sweep_output as u8) * waveform* ((self.length_counter > 0) as u8)
     }
 }
 
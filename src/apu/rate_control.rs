@@ -0,0 +1,33 @@
+// How much audio we try to keep queued up for the sound thread
+const TARGET_LATENCY_MS: u32 = 50;
+// Maximum pitch deviation we allow ourselves to correct drift, 0.5% is inaudible
+const MAX_RATIO_DEVIATION: f64 = 0.005;
+
+/// Dynamic rate control: instead of resampling at a fixed ratio, we look at how full the
+/// audio queue is and slightly bend the ratio so it stays around the target latency.
+/// This absorbs the small mismatch between the emulated and the real clocks without
+/// ever underrunning (crackles) or piling up latency.
+pub struct RateControl {
+    target_queued_samples: usize,
+}
+
+impl RateControl {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            target_queued_samples: (sample_rate * TARGET_LATENCY_MS / 1000) as usize,
+        }
+    }
+
+    pub fn target_queued_samples(&self) -> usize {
+        self.target_queued_samples
+    }
+
+    /// Returns the factor to apply to the output sample rate, above 1 when the queue is
+    /// running dry and below 1 when it is filling up.
+    pub fn ratio(&self, queued_samples: usize) -> f64 {
+        let fill = queued_samples as f64 / (2 * self.target_queued_samples) as f64;
+        let fill = if fill > 1.0 { 1.0 } else { fill };
+
+        1.0 + MAX_RATIO_DEVIATION * (1.0 - 2.0 * fill)
+    }
+}
@@ -0,0 +1,34 @@
+/// Box-filter downsampler going from the CPU clock rate to the output sample rate.
+/// The fractional phase is carried over between calls so no samples are lost or
+/// duplicated on frame boundaries.
+pub struct Resampler {
+    phase: f64,
+    accumulator: f32,
+    accumulated: u32,
+}
+
+impl Resampler {
+    pub fn new() -> Self {
+        Self {
+            phase: 0.0,
+            accumulator: 0.0,
+            accumulated: 0,
+        }
+    }
+
+    /// `step` is the number of input samples making up one output sample
+    pub fn resample<I: Iterator<Item=f32>>(&mut self, input: I, step: f64, output: &mut Vec<f32>) {
+        for sample in input {
+            self.accumulator += sample;
+            self.accumulated += 1;
+            self.phase += 1.0;
+
+            if self.phase >= step {
+                self.phase -= step;
+                output.push(self.accumulator / self.accumulated as f32);
+                self.accumulator = 0.0;
+                self.accumulated = 0;
+            }
+        }
+    }
+}
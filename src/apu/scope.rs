@@ -0,0 +1,45 @@
+use crate::apu::apu_device::APU_CHANNELS;
+
+pub const SCOPE_LENGTH: usize = 512;
+// One point every that many CPU cycles, so the scope holds a bit more than a video frame
+const SCOPE_DECIMATION: u32 = 64;
+
+/// Rolling history of every channel's level for the debug viewer, in the 0..1 range
+pub struct ApuScope {
+    samples: Vec<[f32; SCOPE_LENGTH]>,
+    position: usize,
+    divider: u32,
+}
+
+impl ApuScope {
+    pub fn new() -> Self {
+        Self {
+            samples: vec![[0.0; SCOPE_LENGTH]; APU_CHANNELS.len()],
+            position: 0,
+            divider: 0,
+        }
+    }
+
+    pub fn push(&mut self, levels: &[f32]) {
+        self.divider += 1;
+        if self.divider < SCOPE_DECIMATION {
+            return;
+        }
+        self.divider = 0;
+
+        for (channel, level) in self.samples.iter_mut().zip(levels.iter()) {
+            channel[self.position] = *level;
+        }
+        self.position = (self.position + 1) % SCOPE_LENGTH;
+    }
+
+    /// Oldest to newest
+    pub fn samples(&self, channel: usize) -> impl Iterator<Item=f32> + '_ {
+        let (newest, oldest) = self.samples[channel].split_at(self.position);
+        oldest.iter().chain(newest.iter()).cloned()
+    }
+
+    pub fn latest(&self, channel: usize) -> f32 {
+        self.samples[channel][(self.position + SCOPE_LENGTH - 1) % SCOPE_LENGTH]
+    }
+}
@@ -3,13 +3,38 @@ use cpal::{StreamData, UnknownTypeOutputBuffer};
 use std::sync::Mutex;
 use std::thread;
 use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::cell::UnsafeCell;
+use crate::apu::audio_sink::{AudioSink, FrameSoundBuffer, StereoSample};
+
+/// Plays the sound through cpal, on its own thread
+pub struct CpalAudioSink {
+    sender: SyncSender<FrameSoundBuffer>,
+    sample_rate: u32,
+    /// Samples sent but not played yet, used to drive the dynamic rate control
+    queued_samples: Arc<AtomicUsize>,
+}
+
+impl AudioSink for CpalAudioSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 
-pub type FrameSoundBuffer = Vec<u8>;
+    fn queued_samples(&self) -> Option<usize> {
+        Some(self.queued_samples.load(Ordering::Relaxed))
+    }
+
+    fn push_frame(&mut self, frame: FrameSoundBuffer) {
+        self.queued_samples.fetch_add(frame.len(), Ordering::Relaxed);
+        self.sender.send(frame);
+    }
+}
 
 /// Creates an synchronous thread to read sound data
-pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
+pub fn launch_sound() -> CpalAudioSink {
     let (tx, rx) = sync_channel::<FrameSoundBuffer>(10);
+    let queued_samples = Arc::new(AtomicUsize::new(0));
 
     let host = cpal::default_host();
     let event_loop = host.event_loop();
@@ -25,11 +50,14 @@ pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
     let stream_id = event_loop.build_output_stream(&device, &format).unwrap();
     event_loop.play_stream(stream_id).expect("failed to play_stream");
 
+    let sample_rate = format.sample_rate.0;
+    let consumed_samples = Arc::clone(&queued_samples);
+
     thread::spawn(move || {
         let mut current_buffer: Option<FrameSoundBuffer> = Option::None;
         let mut current_buffer_pos = 0;
         let mut remaining = 0;
-        let mut last_sampled = 0f32;
+        let mut last_sampled: StereoSample = [0.0, 0.0];
 
         let mut rx = Mutex::new(rx);
         let sample_rate = format.sample_rate.0 as f32;
@@ -49,7 +77,7 @@ pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
                 let sampled = optref.unwrap()[current_buffer_pos];
                 current_buffer_pos += 1;
                 remaining -= 1;
-                let sampled = sampled as f32 / 255.0;
+                consumed_samples.fetch_sub(1, Ordering::Relaxed);
                 last_sampled = sampled;
                 sampled
             } else {
@@ -66,7 +94,6 @@ pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
             let sampled = optref.unwrap()[current_buffer_pos];
             current_buffer_pos += 1;
             remaining -= 1;
-            let sampled = sampled as f32 / 255.0;
             sampled*/
         };
 
@@ -84,26 +111,24 @@ pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
                 cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::U16(mut buffer) } => {
                     for sample in buffer.chunks_mut(format.channels as usize) {
                         let sampled = next_value();
-
-                        let value = ((sampled * 0.5 + 0.5) * std::u16::MAX as f32) as u16;
-                        for out in sample.iter_mut() {
-                            *out = value;
+                        for (channel, out) in sample.iter_mut().enumerate() {
+                            *out = ((channel_value(&sampled, channel) * 0.5 + 0.5) * std::u16::MAX as f32) as u16;
                         }
                     }
                 }
                 cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::I16(mut buffer) } => {
                     for sample in buffer.chunks_mut(format.channels as usize) {
-                        let value = (next_value() * std::i16::MAX as f32) as i16;
-                        for out in sample.iter_mut() {
-                            *out = value;
+                        let sampled = next_value();
+                        for (channel, out) in sample.iter_mut().enumerate() {
+                            *out = (channel_value(&sampled, channel) * std::i16::MAX as f32) as i16;
                         }
                     }
                 }
                 cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::F32(mut buffer) } => {
                     for sample in buffer.chunks_mut(format.channels as usize) {
-                        let value = next_value();
-                        for out in sample.iter_mut() {
-                            *out = value;
+                        let sampled = next_value();
+                        for (channel, out) in sample.iter_mut().enumerate() {
+                            *out = channel_value(&sampled, channel);
                         }
                     }
                 }
@@ -114,12 +139,24 @@ pub fn launch_sound() -> SyncSender<FrameSoundBuffer> {
         });
     });
 
-    return tx;
+    return CpalAudioSink {
+        sender: tx,
+        sample_rate,
+        queued_samples,
+    };
+}
+
+/// Maps our stereo samples onto however many channels the device has
+fn channel_value(sample: &StereoSample, channel: usize) -> f32 {
+    match channel {
+        0 => sample[0],
+        _ => sample[1],
+    }
 }
 
 /// Feed garbage to test the async sound routines
-pub fn garbage_test(output: &mut SyncSender<FrameSoundBuffer>) {
-    let mut garbage_data = vec![0u8; 100000];
-    garbage_data.iter_mut().for_each(|s| { *s = rand::random::<u8>(); });
-    output.send(garbage_data);
+pub fn garbage_test(output: &mut dyn AudioSink) {
+    let mut garbage_data = vec![[0f32; 2]; 100000];
+    garbage_data.iter_mut().for_each(|s| { *s = [rand::random::<f32>(), rand::random::<f32>()]; });
+    output.push_frame(garbage_data);
 }
\ No newline at end of file
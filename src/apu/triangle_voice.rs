@@ -11,13 +11,15 @@ bf!(TriangleVoiceReg4[u8]{
 pub struct TriangleVoice {
     pub control_enabled: bool,
     pub length_counter: u16,
+    /// Periods under 2 make a ~55kHz tone that only pops once resampled
+    pub suppress_ultrasonic: bool,
 
     register1: TriangleVoiceReg1,
     register3: u8,
     register4: TriangleVoiceReg4,
 
     linear_counter: u8,
-    linear_counter_halt_flag: bool,
+    linear_counter_reload_flag: bool,
 
     timer: u16,
 
@@ -28,6 +30,7 @@ impl TriangleVoice {
     pub fn new() -> Self {
         Self {
             control_enabled: false,
+            suppress_ultrasonic: false,
 
             register1: TriangleVoiceReg1::new(0),
             register3: 0,
@@ -36,7 +39,7 @@ impl TriangleVoice {
             length_counter: 0,
 
             linear_counter: 0,
-            linear_counter_halt_flag: false,
+            linear_counter_reload_flag: false,
 
             timer: 0,
 
@@ -58,7 +61,7 @@ impl TriangleVoice {
                     self.length_counter = LENGTH_COUNTER_LOOKUP_TABLE[(length_key >> 1) as usize][(length_key & 0x01) as usize] as u16;
                 }
 
-                self.linear_counter_halt_flag = true;
+                self.linear_counter_reload_flag = true;
             }
             _ => {}
         }
@@ -74,15 +77,17 @@ impl TriangleVoice {
         }
     }
 
+    // The reload flag is set by writing $400B, and only gets cleared when the control flag
+    // is clear. With the control flag set the counter is reloaded on every clock.
     pub fn clock_linear_counter(&mut self) {
-        if self.linear_counter_halt_flag {
+        if self.linear_counter_reload_flag {
             self.linear_counter = self.register1.linear_counter_reload_value();
         } else if self.linear_counter > 0 {
             self.linear_counter -= 1;
         }
 
         if self.register1.control() == 0 {
-            self.linear_counter_halt_flag = false;
+            self.linear_counter_reload_flag = false;
         }
     }
 
@@ -91,7 +96,9 @@ impl TriangleVoice {
         //println!("{} {} {}", self.register2, self.register3.val, voice_period);
         //let voice_period = 40;
         if self.timer == 0 {
-            if self.linear_counter > 0 && self.length_counter > 0 {
+            // The sequencer halts on its current step rather than going silent
+            let ultrasonic = self.suppress_ultrasonic && self.period() < 2;
+            if self.linear_counter > 0 && self.length_counter > 0 && !ultrasonic {
                 self.output_sequencer = (self.output_sequencer + 1) % 32;
             }
 
@@ -100,6 +107,14 @@ impl TriangleVoice {
         self.timer -= 1;
     }
 
+    pub fn period(&self) -> u16 {
+        (self.register3 as u16) | ((self.register4.period_high() as u16) << 8)
+    }
+
+    pub fn linear_counter(&self) -> u8 {
+        self.linear_counter
+    }
+
     pub fn output(&self) -> u8 {
         TRIANGLE_VOICE_OUTPUT_SEQUENCE[self.output_sequencer as usize]
     }
@@ -0,0 +1,95 @@
+use crate::apu::audio_sink::{AudioSink, FrameSoundBuffer};
+use crate::apu::resampler::Resampler;
+use crate::tools::wav_writer::{WavWriter, WavSampleFormat};
+
+/// Corner frequency of the last high-pass filter on the console's audio output
+const DC_BLOCKER_CUTOFF: f64 = 37.0;
+
+/// The APU output only goes from 0 to 1, a WAV file wants it centered around 0.
+/// First order high-pass, removes the DC offset the same way the console output does.
+struct DcBlocker {
+    coefficient: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl DcBlocker {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            coefficient: (-2.0 * std::f64::consts::PI * DC_BLOCKER_CUTOFF / sample_rate as f64).exp() as f32,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        let output = input - self.previous_input + self.coefficient * self.previous_output;
+        self.previous_input = input;
+        self.previous_output = output;
+        return output;
+    }
+}
+
+/// File-backed sink, to run without a sound card (headless rendering, regression tests...)
+pub struct WavAudioSink {
+    writer: WavWriter,
+    sample_rate: u32,
+    dc_blockers: [DcBlocker; 2],
+}
+
+impl WavAudioSink {
+    pub fn new(filename: &str, sample_rate: u32, format: WavSampleFormat) -> Self {
+        Self {
+            writer: WavWriter::create(filename, sample_rate, 2, format),
+            sample_rate,
+            dc_blockers: [DcBlocker::new(sample_rate), DcBlocker::new(sample_rate)],
+        }
+    }
+}
+
+impl AudioSink for WavAudioSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queued_samples(&self) -> Option<usize> {
+        None
+    }
+
+    fn push_frame(&mut self, frame: FrameSoundBuffer) {
+        let [left, right] = &mut self.dc_blockers;
+        let frame: FrameSoundBuffer = frame.iter().map(|[l, r]| [left.filter(*l), right.filter(*r)]).collect();
+        self.writer.write_stereo_samples(&frame);
+    }
+}
+
+/// Records the mixed output while the emulator keeps playing through its regular sink.
+/// Resamples on its own from the CPU rate, so the recording has exactly the requested rate
+/// and none of the dynamic rate control wobble.
+pub struct WavRecorder {
+    writer: WavWriter,
+    sample_rate: u32,
+    resampler: Resampler,
+    dc_blocker: DcBlocker,
+}
+
+impl WavRecorder {
+    pub fn new(filename: &str, sample_rate: u32, channels: u16, format: WavSampleFormat) -> Self {
+        Self {
+            writer: WavWriter::create(filename, sample_rate, channels, format),
+            sample_rate,
+            resampler: Resampler::new(),
+            dc_blocker: DcBlocker::new(sample_rate),
+        }
+    }
+
+    pub fn record(&mut self, cpu_rate_samples: &[f32], cpu_clock_rate: f64) {
+        let mut resampled = Vec::<f32>::with_capacity(1024);
+        let step = cpu_clock_rate / self.sample_rate as f64;
+        self.resampler.resample(cpu_rate_samples.iter().cloned(), step, &mut resampled);
+
+        let dc_blocker = &mut self.dc_blocker;
+        let frame: FrameSoundBuffer = resampled.iter().map(|s| dc_blocker.filter(*s)).map(|s| [s, s]).collect();
+        self.writer.write_stereo_samples(&frame);
+    }
+}
@@ -1,12 +1,14 @@
 use crate::cpu::Cpu;
-use std::cell::RefCell;
-use crate::ppu::{Ppu, PpuOutput};
+use std::cell::{RefCell, Cell};
+use crate::ppu::{Ppu, FrameOutput};
 use crate::cartdrige::Cartdrige;
 use std::rc::Rc;
 use crate::input::{Controllers, InputProvider};
 use crate::apu::apu_device::Apu;
-use std::sync::mpsc::SyncSender;
-use crate::apu::streaming_audio::FrameSoundBuffer;
+use crate::apu::audio_sink::AudioSink;
+use crate::region::Region;
+use crate::memory_map::{MemoryMap, MemoryRegion, BusDevice};
+use crate::ppu::event_log::{EventLog, PpuEventKind};
 
 pub struct Bus {
     pub cpu: RefCell<Cpu>,
@@ -15,18 +17,35 @@ pub struct Bus {
     pub apu: RefCell<Apu>,
     pub cartdrige: RefCell<Option<Box<dyn Cartdrige>>>,
     pub controllers: RefCell<Controllers>,
+    pub memory_map: MemoryMap,
+    /// Last value seen on the CPU data bus, what unmapped reads return
+    pub open_bus: Cell<u8>,
+    /// Register writes and interrupts on the PPU timeline, for the event viewer
+    pub ppu_events: RefCell<EventLog>,
 
+    /// Counts PPU dots
     pub master_clock_counter: u64,
+    pub cpu_clock_counter: u64,
+    // Master clock ticks into the current CPU cycle, PAL and Dendy don't divide evenly in dots
+    cpu_clock_phase: u32,
+    pub region: Region,
 
     dma: RefCell<Dma>,
 }
 
+// The DMA unit works on get (even) and put (odd) CPU cycles. OAM DMA halts the CPU for one
+// cycle, maybe waits one more to line up on a get cycle, then does 256 get/put pairs.
 struct Dma {
     dma_page: u8,
     dma_addr: u8,
     dma_data: u8,
     is_doing_dma: bool,
-    dma_dummy: bool,
+    halted: bool,
+    has_data: bool,
+
+    // DMC sample fetches steal cycles on their own, interleaved with the OAM DMA
+    dmc_address: u16,
+    dmc_stall_cycles: u8,
 }
 
 impl Dma {
@@ -37,13 +56,17 @@ impl Dma {
             dma_data: 0,
 
             is_doing_dma: false,
-            dma_dummy: true,
+            halted: false,
+            has_data: false,
+
+            dmc_address: 0,
+            dmc_stall_cycles: 0,
         }
     }
 }
 
 impl Bus where {
-    pub fn new(input_provider: Rc<dyn InputProvider>, graphical_output: Rc<dyn PpuOutput>, audio_output: SyncSender<FrameSoundBuffer>) -> Self {
+    pub fn new(input_provider: Rc<dyn InputProvider>, graphical_output: Rc<dyn FrameOutput>, audio_output: Box<dyn AudioSink>) -> Self {
         let mut bus = Bus {
             cpu: RefCell::new(Cpu::new()),
             cpu_ram: RefCell::new([0; 2048]),
@@ -51,8 +74,14 @@ impl Bus where {
             apu: RefCell::new(Apu::new(audio_output)),
             cartdrige: RefCell::new(Option::None),
             controllers: RefCell::new(Controllers::new(input_provider)),
+            memory_map: MemoryMap::new(),
+            open_bus: Cell::new(0),
+            ppu_events: RefCell::new(EventLog::new()),
 
             master_clock_counter: 0,
+            cpu_clock_counter: 0,
+            cpu_clock_phase: 0,
+            region: Region::Ntsc,
 
             dma: RefCell::new(Dma::new()),
         };
@@ -63,91 +92,142 @@ impl Bus where {
     }
 
     pub fn cpu_read(&self, address: u16, read_only: bool) -> u8 {
-        let mut data = 0_u8;
-        {
-            let mut cart_brw = self.cartdrige.borrow_mut();
-            let cart_ref = cart_brw.as_mut();
-            if cart_ref.is_some() && cart_ref.unwrap().cpu_read(address, &mut data) {
-                return data;
-            }
+        let data = self.cpu_read_inner(address, read_only);
+        // $4015 is inside the CPU, reading it doesn't drive the external data bus
+        if !read_only && address != 0x4015 {
+            self.open_bus.set(data);
         }
+        return data;
+    }
 
-        if address >= 0x0000u16 && address < 0x1FFFu16 {
-            data = self.cpu_ram.borrow()[(address & 0x07FF) as usize]
-        } else if address >= 0x2000u16 && address < 0x3FFFu16 {
-            data = self.ppu.borrow_mut().read_ppu_register(self, address & 0x0007, read_only);
-        } else if address >= 0x4016 && address <= 0x4017 {
-            self.controllers.borrow_mut().read(address, &mut data);
-        } else if address == 0x4015 {
-            self.apu.borrow_mut().cpu_read(address, &mut data);
+    // Anything that doesn't drive all the bits leaves the open bus value in the others
+    fn cpu_read_inner(&self, address: u16, read_only: bool) -> u8 {
+        let mut data = self.open_bus.get();
+        match self.memory_map.lookup_read(address) {
+            Some((BusDevice::CpuRam, address)) => {
+                data = self.cpu_ram.borrow()[address as usize];
+            }
+            Some((BusDevice::PpuRegisters, address)) => {
+                data = self.ppu.borrow_mut().read_ppu_register(self, address, read_only);
+            }
+            Some((BusDevice::ApuStatus, address)) => {
+                self.apu.borrow_mut().cpu_read(address, &mut data);
+            }
+            Some((BusDevice::Controllers, address)) => {
+                self.controllers.borrow_mut().read(address, &mut data);
+            }
+            Some((BusDevice::Cartdrige, address)) => {
+                if let Some(cart) = self.cartdrige.borrow_mut().as_mut() {
+                    cart.cpu_read(address, &mut data);
+                }
+            }
+            _ => {}
         }
 
         return data;
     }
 
     pub fn cpu_write(&self, address: u16, data: u8) {
-        {
-            let mut cart_brw = self.cartdrige.borrow_mut();
-            let cart_ref = cart_brw.as_mut();
-            if cart_ref.is_some() && cart_ref.unwrap().cpu_write(address, data) {
-                return;
-            }
+        self.open_bus.set(data);
+        let mapping = self.memory_map.lookup_write(address);
+        if self.ppu_events.borrow().is_enabled() {
+            self.log_write_event(mapping, data);
         }
-        if address >= 0x0000u16 && address < 0x1FFFu16 {
-            self.cpu_ram.borrow_mut()[(address & 0x07FF) as usize] = data;
-            //println!("write ok {}, {}", address, data);
-        } else if address >= 0x2000u16 && address < 0x3FFFu16 {
-            self.ppu.borrow_mut().write_ppu_register(self, address & 0x0007, data);
-        } else if address == 0x4014 {
-            let mut dma = self.dma.borrow_mut();
-            dma.dma_page = data;
-            dma.dma_addr = 0x00;
-            dma.is_doing_dma = true;
-        } else if address == 0x4016 {
-            self.controllers.borrow_mut().write(address, data);
-        } else if address >= 0x4000 && address <= 0x4017 {
-            self.apu.borrow_mut().cpu_write(self, address, data);
+        match mapping {
+            Some((BusDevice::CpuRam, address)) => {
+                self.cpu_ram.borrow_mut()[address as usize] = data;
+            }
+            Some((BusDevice::PpuRegisters, address)) => {
+                self.ppu.borrow_mut().write_ppu_register(self, address, data);
+            }
+            Some((BusDevice::ApuRegisters, address)) | Some((BusDevice::ApuStatus, address)) => {
+                self.apu.borrow_mut().cpu_write(self, address, data);
+            }
+            Some((BusDevice::OamDma, _)) => {
+                // Starts once the writing instruction is over
+                let mut dma = self.dma.borrow_mut();
+                dma.dma_page = data;
+                dma.dma_addr = 0x00;
+                dma.is_doing_dma = true;
+                dma.halted = false;
+                dma.has_data = false;
+            }
+            Some((BusDevice::Controllers, address)) => {
+                self.controllers.borrow_mut().write(address, data);
+            }
+            Some((BusDevice::Cartdrige, address)) => {
+                if let Some(cart) = self.cartdrige.borrow_mut().as_mut() {
+                    cart.cpu_write(address, data);
+                }
+            }
+            None => {}
         }
     }
 
+    fn log_write_event(&self, mapping: Option<(BusDevice, u16)>, data: u8) {
+        let kind = match mapping {
+            Some((BusDevice::PpuRegisters, address)) => PpuEventKind::RegisterWrite(address as u8),
+            Some((BusDevice::OamDma, _)) => PpuEventKind::OamDma,
+            // Leaving out the PRG RAM
+            Some((BusDevice::Cartdrige, address)) if address < 0x6000 || address >= 0x8000 => PpuEventKind::MapperWrite(address),
+            _ => return,
+        };
+        let (scanline, cycle) = self.ppu.borrow().position();
+        self.ppu_events.borrow_mut().log(kind, scanline, cycle, data);
+    }
+
     pub fn load_cartdrige(&mut self, cart: Box<dyn Cartdrige>) {
+        self.memory_map.unmap_device(BusDevice::Cartdrige);
+        for (start, end) in cart.cpu_address_ranges() {
+            self.memory_map.map_read(MemoryRegion::new(start, end, 0xFFFF, BusDevice::Cartdrige));
+            self.memory_map.map_write(MemoryRegion::new(start, end, 0xFFFF, BusDevice::Cartdrige));
+        }
         *self.cartdrige.borrow_mut() = Option::Some(cart);
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.borrow_mut().set_region(region);
+        self.apu.borrow_mut().set_region(region);
+    }
+
+    /// True when the next call to `clock` will also clock the CPU
+    pub fn is_cpu_cycle_due(&self) -> bool {
+        self.cpu_clock_phase < self.region.ppu_clock_divider()
+    }
+
     pub fn clock(&mut self) {
         self.ppu.borrow_mut().clock(self);
 
-        if self.master_clock_counter % 3 == 0 {
-            if self.dma.borrow().is_doing_dma {
-                let mut dma = self.dma.borrow_mut();
-                if dma.dma_dummy {
-                    if self.master_clock_counter % 2 == 1 {
-                        dma.dma_dummy = false;
-                    }
-                } else {
-                    if self.master_clock_counter % 2 == 0 {
-                        dma.dma_data = self.cpu_read((dma.dma_page as u16) << 8 | dma.dma_addr as u16, false);
-                    } else {
-                        self.ppu.borrow_mut().borrow_oam_raw()[dma.dma_addr as usize] = dma.dma_data;
-                        dma.dma_addr = dma.dma_addr.wrapping_add(1);
-
-                        if dma.dma_addr == 0 {
-                            dma.dma_dummy = true;
-                            dma.is_doing_dma = false;
-                        }
+        if self.is_cpu_cycle_due() {
+            self.clock_cpu_or_dma();
+
+            let (expansion_audio, cartdrige_irq) = {
+                let mut cart_brw = self.cartdrige.borrow_mut();
+                match cart_brw.as_mut() {
+                    Some(cart) => {
+                        cart.clock_cpu();
+                        (cart.expansion_audio_output(), cart.irq_pending())
                     }
+                    None => (0.0, false)
                 }
-            } else {
-                self.cpu.borrow_mut().clock(self);
-            }
+            };
 
             self.apu.borrow_mut().clock_main(12);
-            self.apu.borrow_mut().clock_cpu_clock();
+            self.apu.borrow_mut().clock_cpu_clock(expansion_audio);
 
-            if self.apu.borrow().is_raising_interrupt() {
+            let irq = self.apu.borrow().is_raising_interrupt() || cartdrige_irq;
+            if self.ppu_events.borrow().is_enabled() {
+                let (scanline, cycle) = self.ppu.borrow().position();
+                self.ppu_events.borrow_mut().log_irq_line(irq, scanline, cycle);
+            }
+            if irq {
                 self.cpu.borrow_mut().irq(self);
             }
+
+            self.cpu_clock_counter += 1;
         }
+        self.cpu_clock_phase = (self.cpu_clock_phase + self.region.ppu_clock_divider()) % self.region.cpu_clock_divider();
 
         let mut do_ppu_nmi = false;
         let mut do_ppu_irq = false;
@@ -164,6 +244,8 @@ impl Bus where {
         }
 
         if do_ppu_nmi {
+            let (scanline, cycle) = self.ppu.borrow().position();
+            self.ppu_events.borrow_mut().log(PpuEventKind::Nmi, scanline, cycle, 0);
             self.cpu.borrow_mut().nmi(self);
         }
         if do_ppu_irq {
@@ -174,9 +256,63 @@ impl Bus where {
         self.master_clock_counter += 1;
     }
 
+    // Runs one CPU cycle, unless a DMA has the bus
+    fn clock_cpu_or_dma(&self) {
+        let get_cycle = self.cpu_clock_counter % 2 == 0;
+        let at_boundary = self.cpu.borrow().is_at_instruction_boundary();
+        let mut dma = self.dma.borrow_mut();
+
+        if dma.dmc_stall_cycles == 0 && at_boundary {
+            if let Some(address) = self.apu.borrow_mut().dmc_dma_request() {
+                dma.dmc_address = address;
+                // Halt, dummy, optional alignment, then the get cycle: 3 or 4 cycles.
+                // An ongoing OAM DMA already halted the CPU, only the get (and its alignment) is left,
+                // the OAM DMA then needs a put cycle to realign, so it usually costs 2 cycles.
+                dma.dmc_stall_cycles = if dma.is_doing_dma { 1 } else { 3 };
+            }
+        }
+
+        if dma.dmc_stall_cycles > 0 {
+            // The fetch itself has to land on a get cycle
+            if dma.dmc_stall_cycles == 1 && !get_cycle {
+                return;
+            }
+            dma.dmc_stall_cycles -= 1;
+            if dma.dmc_stall_cycles == 0 {
+                let data = self.cpu_read(dma.dmc_address, false);
+                self.apu.borrow_mut().dmc_dma_complete(data);
+            }
+            return;
+        }
+
+        if dma.is_doing_dma && at_boundary {
+            if !dma.halted {
+                dma.halted = true;
+            } else if get_cycle && !dma.has_data {
+                dma.dma_data = self.cpu_read((dma.dma_page as u16) << 8 | dma.dma_addr as u16, false);
+                dma.has_data = true;
+            } else if !get_cycle && dma.has_data {
+                // Goes through $2004, so it lands at OAMADDR
+                self.ppu.borrow_mut().write_ppu_register(self, 0x0004, dma.dma_data);
+                dma.has_data = false;
+                dma.dma_addr = dma.dma_addr.wrapping_add(1);
+                if dma.dma_addr == 0 {
+                    dma.is_doing_dma = false;
+                }
+            }
+            // Otherwise it's the alignment cycle
+            return;
+        }
+
+        drop(dma);
+        self.cpu.borrow_mut().clock(self);
+    }
+
     pub fn reset(&mut self) {
         self.cpu.borrow_mut().reset(self);
         self.ppu.borrow_mut().reset(self);
         self.master_clock_counter = 0;
+        self.cpu_clock_counter = 0;
+        self.cpu_clock_phase = 0;
     }
 }
\ No newline at end of file
@@ -3,9 +3,27 @@ use crate::ines_loader::INesHeaderInfo;
 pub trait Cartdrige {
     fn get_info(&self) -> &INesHeaderInfo;
 
+    /// Inclusive (start, end) CPU address ranges the cartdrige gets mapped to in the `MemoryMap`, for reads and writes
+    fn cpu_address_ranges(&self) -> Vec<(u16, u16)> {
+        vec![(0x4020, 0xFFFF)]
+    }
+
     fn cpu_read(&mut self, address: u16, data: &mut u8) -> bool;
     fn cpu_write(&mut self, address: u16, data: u8) -> bool;
 
     fn ppu_read(&mut self, address: u16, data: &mut u8) -> (bool, bool);
     fn ppu_write(&mut self, address: u16, data: u8) -> bool;
-}
\ No newline at end of file
+
+    /// Called on every CPU cycle, for cartdriges with their own timers or sound channels
+    fn clock_cpu(&mut self) {}
+
+    /// Cartdriges with CPU cycle based IRQ counters hold the IRQ line through this
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Output of the cartdrige's sound channels, on the same scale as the APU mixer
+    fn expansion_audio_output(&self) -> f32 {
+        0.0
+    }
+}
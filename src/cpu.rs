@@ -72,6 +72,11 @@ impl Cpu {
         self.rem_cycles -= 1;
     }
 
+    /// DMAs can only halt the CPU once the current instruction is done
+    pub fn is_at_instruction_boundary(&self) -> bool {
+        self.rem_cycles == 0
+    }
+
     pub fn reset(&mut self, bus: &Bus) {
         self.a = 0;
         self.x = 0;
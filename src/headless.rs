@@ -0,0 +1,21 @@
+use crate::input::InputProvider;
+use crate::ppu::{FrameOutput, Frame};
+
+/// Stands in for the main window when running without any display
+pub struct Headless {}
+
+impl Headless {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl InputProvider for Headless {
+    fn get_button_state(&self, _button: u8, _controller: u8) -> bool {
+        false
+    }
+}
+
+impl FrameOutput for Headless {
+    fn present_frame(&self, _frame: &Frame) {}
+}
@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::{Read, BufReader};
 
 use crate::mappers::{create_cartdrige};
+use crate::region::Region;
 
 const INES_MAGIC_BYTES: &'static str = "NES\u{001a}";
 
@@ -56,6 +57,21 @@ fn read_header(reader: &mut BufReader<File>) -> INesHeaderInfo {
     let padding14 = read_byte(reader);
     let padding15 = read_byte(reader);
 
+    // NES 2.0 has a proper timing field, plain iNES only has a rarely set PAL bit
+    let nes2_header = (flags7 & 0x0C) == 0x08;
+    let region = if nes2_header {
+        match padding12 & 0x03 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            // Multi-region carts run fine as NTSC
+            _ => Region::Ntsc,
+        }
+    } else if (flags9 & 0x01) == 0x01 {
+        Region::Pal
+    } else {
+        Region::Ntsc
+    };
+
     return INesHeaderInfo {
         mapper_type,
         mirroring_mode,
@@ -63,6 +79,7 @@ fn read_header(reader: &mut BufReader<File>) -> INesHeaderInfo {
         trainer_present,
         prg_pages,
         chr_pages,
+        region,
     };
 }
 
@@ -71,6 +88,8 @@ pub enum MirroringMode {
     Horizontal,
     Vertical,
     FourScreen,
+    OneScreenLower,
+    OneScreenUpper,
 }
 
 #[derive(Debug)]
@@ -81,6 +100,7 @@ pub struct INesHeaderInfo {
     pub trainer_present: bool,
     pub prg_pages: u8,
     pub chr_pages: u8,
+    pub region: Region,
     //prg_ram_size: u8,
 }
 
@@ -26,8 +26,9 @@ impl Controllers {
         }
     }
 
+    /// Only the low bits are driven, the top 3 bits keep the open bus value
     pub fn read(&mut self, address: u16, data: &mut u8) {
-        *data = *data & 0xF8;
+        *data = *data & 0xE0;
         let provider: &dyn InputProvider = self.provider.borrow();
         *data = *data | if provider.get_button_state(self.reading_button, 0) { 0x01 } else { 0x00 };
         self.reading_button += 1;
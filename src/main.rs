@@ -6,16 +6,33 @@ use crate::bus::Bus;
 use crate::ines_loader::load_rom_file_as_cartdrige;
 use crate::ppu::patterns_debug_viewer::PatternsDebugWindow;
 use crate::tools::{dump_memory_contents, dump_visual_memory_contents};
-use crate::ppu::main_window::MainWindow;
+use crate::ppu::main_window::{MainWindow, DisplaySettings};
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::ppu::PpuOutput;
+use crate::ppu::FrameOutput;
 use crate::apu::streaming_audio::{launch_sound, garbage_test};
 use std::env;
 use crate::input::InputProvider;
 use crate::ppu::nametables_debug_viewer::NametableDebugWindow;
-use std::time::{Instant, Duration};
+use std::time::{Instant, Duration, SystemTime, UNIX_EPOCH};
 use std::ops::Sub;
+use std::path::Path;
+use crate::headless::Headless;
+use crate::apu::audio_sink::{AudioSink, NullAudioSink};
+use crate::apu::apu_device::{ApuChannel, APU_CHANNELS};
+use crate::apu::wav_sink::WavAudioSink;
+use crate::tools::wav_writer::WavSampleFormat;
+use minifb::{Key, KeyRepeat};
+use crate::nsf_loader::{is_nsf_file, load_nsf_file};
+use crate::nsf_player::{NsfPlayer, DEFAULT_TRACK_LENGTH_MS, DEFAULT_FADE_MS};
+use crate::apu::apu_debug_viewer::ApuDebugWindow;
+use crate::ppu::event_debug_viewer::EventDebugWindow;
+use crate::ppu::oam_debug_viewer::OamDebugWindow;
+use crate::region::Region;
+use crate::ppu::ntsc_filter::{NtscFilter, NtscSetup};
+use crate::ppu::palette::{Palette, PaletteParameters};
+use crate::ppu::frame_renderer::{FrameRenderer, Image};
+use crate::tools::video_recorder::VideoRecorder;
 
 mod bus;
 mod cpu;
@@ -23,60 +40,213 @@ mod ppu;
 mod apu;
 mod input;
 mod cartdrige;
+mod headless;
+mod region;
+mod memory_map;
 
 mod mappers;
 mod ines_loader;
+mod nsf_loader;
+mod nsf_player;
 
 mod tools;
 
 fn main() {
-    let mut audio_tx = launch_sound();
+    let args: Vec<String> = env::args().collect();
 
-    let mut args = env::args();
+    let rom_filename = arg_value(&args, "--rom").unwrap_or("roms/smb3.nes");
+    let rom_name = Path::new(rom_filename).file_stem().and_then(|s| s.to_str()).unwrap_or("rom").to_string();
 
-    let main_window = Rc::new(RefCell::new(MainWindow::new()));
+    let nestest_mode = args.iter().any(|i| { i == "--nestest"});
+    let unlimited_speed = args.iter().any(|i| { i == "-u"});
+    // Let the sound card clock pace the emulation instead of the wall clock
+    let audio_sync = args.iter().any(|i| { i == "--audio-sync"});
+
+    // Audio recording settings
+    let record_audio = arg_value(&args, "--record-audio");
+    let record_sample_rate: u32 = arg_value(&args, "--sample-rate").map(|v| v.parse().expect("invalid sample rate")).unwrap_or(44100);
+    let record_format = arg_value(&args, "--bit-depth")
+        .map(|v| WavSampleFormat::from_bit_depth(v.parse().expect("invalid bit depth")).expect("bit depth should be 8, 16, 24 or 32"))
+        .unwrap_or(WavSampleFormat::Int16);
+
+    // `--record-video name` writes name.y4m and name.wav
+    let record_video = arg_value(&args, "--record-video");
+
+    let headless_mode = args.iter().any(|i| { i == "--headless"});
+    let screenshot_scale: usize = arg_value(&args, "--screenshot-scale").map(|v| v.parse().expect("invalid screenshot scale")).unwrap_or(1);
+    // Otherwise the region comes from the ROM header
+    let forced_region = arg_value(&args, "--region").map(|v| Region::from_name(v).expect("region should be ntsc, pal or dendy"));
+
+    if is_nsf_file(rom_filename) {
+        play_nsf(&args, rom_filename, headless_mode, forced_region, audio_sync, record_audio, record_sample_rate, record_format);
+        return;
+    }
+
+    if headless_mode {
+        // Runs a fixed amount of frames as fast as possible, with nothing but files as output
+        let frames: u32 = arg_value(&args, "--frames").map(|v| v.parse().expect("invalid frame count")).unwrap_or(3600);
+
+        let headless = Rc::new(Headless::new());
+        let audio_sink: Box<dyn AudioSink> = match record_audio {
+            Some(filename) => Box::new(WavAudioSink::new(filename, record_sample_rate, record_format)),
+            None => Box::new(NullAudioSink::new(record_sample_rate)),
+        };
+        let mut nes = Bus::new(
+            Rc::clone(&headless) as Rc<dyn InputProvider>,
+            Rc::clone(&headless) as Rc<dyn FrameOutput>,
+            audio_sink
+        );
+
+        let cartridge = load_rom_file_as_cartdrige(rom_filename);
+        let region = forced_region.unwrap_or(cartridge.get_info().region);
+        nes.load_cartdrige(cartridge);
+        nes.set_region(region);
+        nes.reset();
+        apply_channel_options(&nes, &args, record_sample_rate, record_format);
+
+        // `--screenshot 60,120` saves those frames, counted from 0
+        let screenshot_frames: Vec<u64> = arg_value(&args, "--screenshot")
+            .map(|v| v.split(',').map(|f| f.parse().expect("invalid frame number")).collect())
+            .unwrap_or(Vec::new());
+        let mut renderer = create_frame_renderer(&args, load_palette(&args));
+        let mut image = renderer.create_image();
+        let mut video_recorder = record_video.map(|name| VideoRecorder::start(&nes, name, create_frame_renderer(&args, load_palette(&args)), record_sample_rate, record_format));
+
+        for _ in 0..frames {
+            run_frame(&mut nes);
+            if let Some(video_recorder) = video_recorder.as_mut() {
+                video_recorder.record_frame(&nes);
+            }
+
+            let ppu = nes.ppu.borrow();
+            let frame = ppu.frame();
+            if screenshot_frames.contains(&frame.number) {
+                renderer.render(&frame, &mut image);
+                save_screenshot(&image, &rom_name, frame.number, screenshot_scale);
+            }
+        }
+        if let Some(video_recorder) = video_recorder {
+            video_recorder.stop(&nes);
+        }
+        return;
+    }
+
+    let audio_sink = launch_sound();
+
+    let palette = load_palette(&args);
+    let main_window = Rc::new(RefCell::new(MainWindow::new(create_frame_renderer(&args, palette.clone()), display_settings(&args))));
     let mut nes = Bus::new(
         Rc::clone(&main_window) as Rc<dyn InputProvider>,
-        Rc::clone(&main_window) as Rc<dyn PpuOutput>,
-        audio_tx
+        Rc::clone(&main_window) as Rc<dyn FrameOutput>,
+        Box::new(audio_sink)
     );
 
-    let cartridge = load_rom_file_as_cartdrige("roms/smb3.nes");
+    let cartridge = load_rom_file_as_cartdrige(rom_filename);
+    let region = forced_region.unwrap_or(cartridge.get_info().region);
+    println!("Running as {}", region.name());
     nes.load_cartdrige(cartridge);
+    nes.set_region(region);
     nes.reset();
 
-    let nestest_mode = args.find(|i| { i == "--nestest"}).is_some();
-    let unlimited_speed = args.find(|i| { i == "-u"}).is_some();
-    let fps = 60;
-
-    let ideal_frame_duration = Duration::from_micros(1_000_000 / fps);
+    if let Some(filename) = record_audio {
+        nes.apu.borrow_mut().start_recording(filename, record_sample_rate, record_format);
+    }
+    apply_channel_options(&nes, &args, record_sample_rate, record_format);
+    let mut video_recorder = record_video.map(|name| VideoRecorder::start(&nes, name, create_frame_renderer(&args, palette.clone()), record_sample_rate, record_format));
 
     if !nestest_mode {
-        let mut pattern_debug_window = PatternsDebugWindow::new();
-        let mut nametable_debug_window = NametableDebugWindow::new();
+        let mut pattern_debug_window = PatternsDebugWindow::new(palette.clone());
+        let mut nametable_debug_window = NametableDebugWindow::new(palette.clone());
+        let mut apu_debug_window = open_apu_debug_window(&nes, &args);
+        let mut event_debug_window = open_event_debug_window(&nes, &args);
+        // `--oam-debug` lists the sprites, and outlines them on the main window
+        let mut oam_debug_window = if args.iter().any(|i| { i == "--oam-debug"}) { Some(OamDebugWindow::new(palette.clone())) } else { None };
 
         while main_window.borrow().window.is_open() {
             let frame_start_time = Instant::now();
 
             let instr_prev = nes.master_clock_counter;
-            while !nes.ppu.borrow().frame_complete {
-                nes.clock();
-            }
-            nes.apu.borrow_mut().frame_done();
+            run_frame(&mut nes);
             //println!("{}", nes.master_clock_counter - instr_prev);
+            if let Some(video_recorder) = video_recorder.as_mut() {
+                video_recorder.record_frame(&nes);
+            }
 
-            nes.ppu.borrow_mut().frame_complete = false;
             pattern_debug_window.update(&nes);
             nametable_debug_window.update(&nes);
+            if let Some(apu_debug_window) = apu_debug_window.as_mut() {
+                apu_debug_window.update(&nes);
+            }
+            if let Some(event_debug_window) = event_debug_window.as_mut() {
+                event_debug_window.update(&nes);
+            }
+            if let Some(oam_debug_window) = oam_debug_window.as_mut() {
+                oam_debug_window.update(&nes);
+                main_window.borrow_mut().set_sprite_boxes(oam_debug_window.sprite_boxes());
+            }
             main_window.borrow_mut().refresh();
 
-            let frame_done_time = Instant::now();
-            let frame_computing_duration = Instant::duration_since(&frame_done_time, frame_start_time);
+            // F9 toggles audio recording
+            if main_window.borrow().window.is_key_pressed(Key::F9, KeyRepeat::No) {
+                let mut apu = nes.apu.borrow_mut();
+                if apu.is_recording() {
+                    apu.stop_recording();
+                } else {
+                    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                    apu.start_recording(&format!("{}_{}.wav", rom_name, timestamp), record_sample_rate, record_format);
+                }
+            }
+
+            // F11 toggles video recording
+            if main_window.borrow().window.is_key_pressed(Key::F11, KeyRepeat::No) {
+                match video_recorder.take() {
+                    Some(recorder) => recorder.stop(&nes),
+                    None => {
+                        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                        let name = format!("{}_{}", rom_name, timestamp);
+                        video_recorder = Some(VideoRecorder::start(&nes, &name, create_frame_renderer(&args, palette.clone()), record_sample_rate, record_format));
+                    }
+                }
+            }
+
+            // F12 saves what's on screen
+            if main_window.borrow().window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                let main_window = main_window.borrow();
+                save_screenshot(main_window.image(), &rom_name, main_window.frame_number(), screenshot_scale);
+            }
+
+            // F10 toggles the per-channel capture
+            if main_window.borrow().window.is_key_pressed(Key::F10, KeyRepeat::No) {
+                let mut apu = nes.apu.borrow_mut();
+                if apu.is_capturing_channels() {
+                    apu.stop_channel_capture();
+                } else {
+                    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                    apu.start_channel_capture(&format!("{}_{}", rom_name, timestamp), record_sample_rate, record_format);
+                }
+            }
 
-            if frame_computing_duration < ideal_frame_duration {
-                let sleep_duration = ideal_frame_duration.sub(frame_computing_duration);
-                spin_sleep::sleep(sleep_duration);
+            // 1-6 toggle muting a channel, shift+1-6 soloes it
+            {
+                let main_window = main_window.borrow();
+                let window = &main_window.window;
+                let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+                let channel_keys = [Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6];
+                for (key, channel) in channel_keys.iter().zip(APU_CHANNELS.iter()) {
+                    if window.is_key_pressed(*key, KeyRepeat::No) {
+                        let mut apu = nes.apu.borrow_mut();
+                        if shift {
+                            let solo = if apu.solo_channel() == Some(*channel) { None } else { Some(*channel) };
+                            apu.set_solo_channel(solo);
+                        } else {
+                            let muted = !apu.is_channel_muted(*channel);
+                            apu.set_channel_muted(*channel, muted);
+                        }
+                    }
+                }
             }
+
+            pace_frame(&nes, audio_sync, frame_start_time);
         }
     } else {
         // Jump to nestest routine
@@ -86,6 +256,236 @@ fn main() {
         }
     }
 
+    if let Some(video_recorder) = video_recorder {
+        video_recorder.stop(&nes);
+    }
+    nes.apu.borrow_mut().stop_recording();
+    nes.apu.borrow_mut().stop_channel_capture();
+
     dump_memory_contents(&nes, "mem.bin");
     dump_visual_memory_contents(&nes, "ppu_mem.bin");
 }
+
+/// Plays a NSF rip, or renders one of its tracks when running headless.
+/// Left and right arrows switch tracks while playing.
+fn play_nsf(args: &[String], filename: &str, headless: bool, forced_region: Option<Region>, audio_sync: bool, record_audio: Option<&str>, record_sample_rate: u32, record_format: WavSampleFormat) {
+    let nsf = match load_nsf_file(filename) {
+        Ok(nsf) => nsf,
+        Err(e) => {
+            eprintln!("Can't play {}: {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    println!("{} - {} ({})", nsf.info.name, nsf.info.artist, nsf.info.copyright);
+
+    let length_ms = arg_value(args, "--length").map(|v| v.parse::<u32>().expect("invalid length") * 1000).unwrap_or(DEFAULT_TRACK_LENGTH_MS);
+    let fade_ms = arg_value(args, "--fade").map(|v| v.parse::<u32>().expect("invalid fade") * 1000).unwrap_or(DEFAULT_FADE_MS);
+    let region = forced_region.unwrap_or(if nsf.info.pal { Region::Pal } else { Region::Ntsc });
+    let mut player = NsfPlayer::new(nsf, region, length_ms, fade_ms);
+
+    // Track numbers are 1-based on the command line, like in every other player
+    let total_songs = player.info().total_songs;
+    let track = match arg_value(args, "--track").map(|v| v.parse::<u8>().expect("invalid track number")) {
+        Some(track) if track == 0 || track > total_songs => {
+            eprintln!("Track {} doesn't exist, {} has {} tracks", track, filename, total_songs);
+            std::process::exit(1);
+        }
+        Some(track) => track - 1,
+        None => player.info().starting_song,
+    };
+
+    if headless {
+        let headless = Rc::new(Headless::new());
+        let audio_sink: Box<dyn AudioSink> = match record_audio {
+            Some(filename) => Box::new(WavAudioSink::new(filename, record_sample_rate, record_format)),
+            None => Box::new(NullAudioSink::new(record_sample_rate)),
+        };
+        let mut nes = Bus::new(
+            Rc::clone(&headless) as Rc<dyn InputProvider>,
+            Rc::clone(&headless) as Rc<dyn FrameOutput>,
+            audio_sink
+        );
+        apply_channel_options(&nes, args, record_sample_rate, record_format);
+
+        player.start_track(&mut nes, track);
+        while !player.is_track_finished() {
+            player.run_frame(&mut nes);
+        }
+        return;
+    }
+
+    let main_window = Rc::new(RefCell::new(MainWindow::new(FrameRenderer::new(Palette::new(), None), display_settings(args))));
+    let mut nes = Bus::new(
+        Rc::clone(&main_window) as Rc<dyn InputProvider>,
+        Rc::clone(&main_window) as Rc<dyn FrameOutput>,
+        Box::new(launch_sound())
+    );
+    if let Some(filename) = record_audio {
+        nes.apu.borrow_mut().start_recording(filename, record_sample_rate, record_format);
+    }
+    apply_channel_options(&nes, args, record_sample_rate, record_format);
+
+    let mut apu_debug_window = open_apu_debug_window(&nes, args);
+
+    player.start_track(&mut nes, track);
+    while main_window.borrow().window.is_open() {
+        let frame_start_time = Instant::now();
+
+        player.run_frame(&mut nes);
+        main_window.borrow_mut().refresh();
+        if let Some(apu_debug_window) = apu_debug_window.as_mut() {
+            apu_debug_window.update(&nes);
+        }
+
+        let order = player.track_order();
+        let position = order.iter().position(|t| *t == player.track()).unwrap_or(0);
+        let next_pressed = main_window.borrow().window.is_key_pressed(Key::Right, KeyRepeat::No);
+        let previous_pressed = main_window.borrow().window.is_key_pressed(Key::Left, KeyRepeat::No);
+        if next_pressed || player.is_track_finished() {
+            player.start_track(&mut nes, order[(position + 1) % order.len()]);
+        } else if previous_pressed {
+            player.start_track(&mut nes, order[(position + order.len() - 1) % order.len()]);
+        }
+
+        pace_frame(&nes, audio_sync, frame_start_time);
+    }
+
+    nes.apu.borrow_mut().stop_recording();
+    nes.apu.borrow_mut().stop_channel_capture();
+}
+
+/// Waits until it's time to start the next frame, either on the wall clock or the sound card's
+fn pace_frame(nes: &Bus, audio_sync: bool, frame_start_time: Instant) {
+    // NTSC runs at 60.0988 fps, not quite 60, PAL and Dendy at 50.007
+    let ideal_frame_duration = nes.region.frame_duration();
+
+    if audio_sync {
+        while nes.apu.borrow().is_audio_ahead() {
+            spin_sleep::sleep(Duration::from_micros(500));
+        }
+    } else {
+        let frame_done_time = Instant::now();
+        let frame_computing_duration = Instant::duration_since(&frame_done_time, frame_start_time);
+
+        if frame_computing_duration < ideal_frame_duration {
+            let sleep_duration = ideal_frame_duration.sub(frame_computing_duration);
+            spin_sleep::sleep(sleep_duration);
+        }
+    }
+}
+
+/// Runs the emulation up to the end of the current frame, then hands the audio over
+fn run_frame(nes: &mut Bus) {
+    while !nes.ppu.borrow().frame_complete {
+        nes.clock();
+    }
+    nes.apu.borrow_mut().frame_done();
+    nes.ppu_events.borrow_mut().end_frame();
+    nes.ppu.borrow_mut().frame_complete = false;
+}
+
+/// `--palette file.pal` loads a palette, any of `--hue`, `--saturation`, `--contrast`, `--brightness`
+/// and `--gamma` generates one instead. `--save-palette out.pal` writes the result.
+fn load_palette(args: &[String]) -> Palette {
+    let mut parameters = PaletteParameters::new();
+    let mut generate = false;
+    {
+        let mut parameter = |flag: &str, value: &mut f32| {
+            if let Some(v) = arg_value(args, flag) {
+                *value = v.parse().unwrap_or_else(|_| panic!("invalid value for {}", flag));
+                generate = true;
+            }
+        };
+        parameter("--hue", &mut parameters.hue);
+        parameter("--saturation", &mut parameters.saturation);
+        parameter("--contrast", &mut parameters.contrast);
+        parameter("--brightness", &mut parameters.brightness);
+        parameter("--gamma", &mut parameters.gamma);
+    }
+
+    let palette = if generate {
+        Palette::generate(&parameters)
+    } else {
+        match arg_value(args, "--palette") {
+            Some(filename) => Palette::load(filename),
+            None => Palette::new(),
+        }
+    };
+    if let Some(filename) = arg_value(args, "--save-palette") {
+        palette.save(filename);
+    }
+    return palette;
+}
+
+/// `--ntsc composite` (or `svideo`, `rgb`) runs the picture through the NTSC signal filter
+fn create_frame_renderer(args: &[String], palette: Palette) -> FrameRenderer {
+    let ntsc_filter = arg_value(args, "--ntsc").map(|v| NtscFilter::new(NtscSetup::from_name(v).expect("ntsc filter should be composite, svideo or rgb")));
+    return FrameRenderer::new(palette, ntsc_filter);
+}
+
+/// `--scale 3`, `--aspect`, `--crop-overscan` and `--fullscreen`
+fn display_settings(args: &[String]) -> DisplaySettings {
+    let mut settings = DisplaySettings::new();
+    // The NTSC filter has twice the horizontal resolution, it would be a waste not to show it
+    if arg_value(args, "--ntsc").is_some() {
+        settings.scale = 2;
+    }
+    if let Some(scale) = arg_value(args, "--scale") {
+        settings.scale = scale.parse().expect("invalid scale");
+        assert!(settings.scale >= 1 && settings.scale <= 6, "scale should be between 1 and 6");
+    }
+    settings.aspect_correction = args.iter().any(|i| { i == "--aspect"});
+    settings.crop_overscan = args.iter().any(|i| { i == "--crop-overscan"});
+    settings.fullscreen = args.iter().any(|i| { i == "--fullscreen"});
+    return settings;
+}
+
+/// Saves `<rom>_<frame>.png`, `scale` times bigger
+fn save_screenshot(image: &Image, rom_name: &str, frame_number: u64, scale: usize) {
+    let filename = format!("{}_{}.png", rom_name, frame_number);
+    image.scaled(scale).save_png(&filename);
+    println!("Saved {}", filename);
+}
+
+/// `--apu-debug` opens the sound channels viewer
+fn open_apu_debug_window(nes: &Bus, args: &[String]) -> Option<ApuDebugWindow> {
+    if !args.iter().any(|i| { i == "--apu-debug"}) {
+        return None;
+    }
+    nes.apu.borrow_mut().set_scope_enabled(true);
+    Some(ApuDebugWindow::new())
+}
+
+/// `--ppu-events` opens the PPU event viewer
+fn open_event_debug_window(nes: &Bus, args: &[String]) -> Option<EventDebugWindow> {
+    if !args.iter().any(|i| { i == "--ppu-events"}) {
+        return None;
+    }
+    nes.ppu_events.borrow_mut().set_enabled(true);
+    Some(EventDebugWindow::new(nes.region))
+}
+
+/// `--mute pulse1,noise`, `--solo triangle`, `--suppress-ultrasonic` and `--record-channels <prefix>`
+fn apply_channel_options(nes: &Bus, args: &[String], sample_rate: u32, format: WavSampleFormat) {
+    let mut apu = nes.apu.borrow_mut();
+    apu.set_triangle_ultrasonic_suppression(args.iter().any(|i| { i == "--suppress-ultrasonic"}));
+    if let Some(channels) = arg_value(args, "--mute") {
+        for name in channels.split(',') {
+            let channel = ApuChannel::from_name(name).unwrap_or_else(|| panic!("unknown channel: {}", name));
+            apu.set_channel_muted(channel, true);
+        }
+    }
+    if let Some(name) = arg_value(args, "--solo") {
+        let channel = ApuChannel::from_name(name).unwrap_or_else(|| panic!("unknown channel: {}", name));
+        apu.set_solo_channel(Some(channel));
+    }
+    if let Some(prefix) = arg_value(args, "--record-channels") {
+        apu.start_channel_capture(prefix, sample_rate, format);
+    }
+}
+
+/// Value following a command line flag, ie `--rom smb.nes`
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|i| { i == flag })?;
+    args.get(position + 1).map(|s| s.as_str())
+}
@@ -0,0 +1,202 @@
+use crate::cartdrige::Cartdrige;
+use crate::ines_loader::{INesHeaderInfo, MirroringMode};
+use crate::apu::expansion::ExpansionAudio;
+use crate::apu::expansion::vrc6::Vrc6Audio;
+use std::io::{Read, BufReader};
+
+/// Konami VRC6, mapper 24 (VRC6a) and 26 (VRC6b, with A0 and A1 swapped)
+pub fn create_mapper24_cartdrige<T: Read>(header: INesHeaderInfo, reader: &mut BufReader<T>) -> Box<dyn Cartdrige> {
+    let mut prg_data = vec![0; header.prg_pages as usize * 16384];
+    reader.read_exact(&mut prg_data);
+
+    let mut chr_data = vec![0; header.chr_pages as usize * 8192];
+    reader.read_exact(&mut chr_data);
+
+    let swapped_address_lines = header.mapper_type == 26;
+    let prg_8k_banks_count = (prg_data.len() / 8192) as u8;
+
+    return Box::new(Mapper24Cartdrige {
+        header,
+        prg_data,
+        chr_data,
+        prg_ram: [0; 8192],
+
+        swapped_address_lines,
+        prg_8k_banks_count,
+
+        prg_16k_bank: 0,
+        prg_8k_bank: 0,
+        chr_banks: [0; 8],
+        prg_ram_enable: false,
+
+        irq_latch: 0,
+        irq_counter: 0,
+        irq_prescaler: 341,
+        irq_enable: false,
+        irq_enable_after_ack: false,
+        irq_cycle_mode: false,
+        irq_pending: false,
+
+        audio: Vrc6Audio::new(),
+    });
+}
+
+struct Mapper24Cartdrige {
+    header: INesHeaderInfo,
+    prg_data: Vec<u8>,
+    chr_data: Vec<u8>,
+    prg_ram: [u8; 8192],
+
+    swapped_address_lines: bool,
+    prg_8k_banks_count: u8,
+
+    prg_16k_bank: u8,
+    prg_8k_bank: u8,
+    chr_banks: [u8; 8],
+    prg_ram_enable: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_prescaler: i16,
+    irq_enable: bool,
+    irq_enable_after_ack: bool,
+    irq_cycle_mode: bool,
+    irq_pending: bool,
+
+    audio: Vrc6Audio,
+}
+
+impl Mapper24Cartdrige {
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq_pending = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Cartdrige for Mapper24Cartdrige {
+    fn get_info(&self) -> &INesHeaderInfo {
+        return &self.header;
+    }
+
+    fn cpu_read(&mut self, address: u16, data: &mut u8) -> bool {
+        if address >= 0x6000 && address <= 0x7FFF {
+            if self.prg_ram_enable {
+                *data = self.prg_ram[(address & 0x1FFF) as usize];
+            }
+            return true;
+        }
+        if address >= 0x8000u16 {
+            let bank = match address {
+                /* 8000-BFFF */ 0x8000..=0xBFFF => { (self.prg_16k_bank << 1) | ((address >> 13) & 0x01) as u8 }
+                /* C000-DFFF */ 0xC000..=0xDFFF => { self.prg_8k_bank }
+                /* E000-FFFF */ _ => { self.prg_8k_banks_count - 1 }
+            };
+            let address = (((bank % self.prg_8k_banks_count) as u32) * 8192) | (address & 0x1FFF) as u32;
+            *data = self.prg_data[address as usize];
+            return true;
+        }
+        return false;
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address >= 0x6000 && address <= 0x7FFF {
+            if self.prg_ram_enable {
+                self.prg_ram[(address & 0x1FFF) as usize] = data;
+            }
+            return true;
+        }
+        if address < 0x8000u16 {
+            return false;
+        }
+
+        let address = if self.swapped_address_lines {
+            (address & 0xFFFC) | ((address & 0x01) << 1) | ((address & 0x02) >> 1)
+        } else {
+            address
+        };
+
+        if self.audio.write_register(address, data) {
+            return true;
+        }
+
+        match address & 0xF003 {
+            0x8000..=0x8003 => { self.prg_16k_bank = data & 0x0F; }
+            0xB003 => {
+                // Only the plain 1KiB CHR banking mode is supported
+                self.prg_ram_enable = (data & 0x80) != 0;
+                self.header.mirroring_mode = match (data >> 2) & 0x03 {
+                    0 => MirroringMode::Vertical,
+                    1 => MirroringMode::Horizontal,
+                    2 => MirroringMode::OneScreenLower,
+                    _ => MirroringMode::OneScreenUpper,
+                };
+            }
+            0xC000..=0xC003 => { self.prg_8k_bank = data & 0x1F; }
+            0xD000..=0xD003 => { self.chr_banks[(address & 0x03) as usize] = data; }
+            0xE000..=0xE003 => { self.chr_banks[4 + (address & 0x03) as usize] = data; }
+            0xF000 => { self.irq_latch = data; }
+            0xF001 => {
+                self.irq_enable_after_ack = (data & 0x01) != 0;
+                self.irq_enable = (data & 0x02) != 0;
+                self.irq_cycle_mode = (data & 0x04) != 0;
+                if self.irq_enable {
+                    self.irq_counter = self.irq_latch;
+                    self.irq_prescaler = 341;
+                }
+                self.irq_pending = false;
+            }
+            0xF002 => {
+                self.irq_pending = false;
+                self.irq_enable = self.irq_enable_after_ack;
+            }
+            _ => {}
+        }
+        return true;
+    }
+
+    fn ppu_read(&mut self, address: u16, data: &mut u8) -> (bool, bool) {
+        if address <= 0x1FFFu16 {
+            let bank = self.chr_banks[(address >> 10) as usize] as usize;
+            let address = (bank * 1024) | (address & 0x03FF) as usize;
+            *data = if self.chr_data.is_empty() { 0 } else { self.chr_data[address % self.chr_data.len()] };
+            return (true, false);
+        }
+        return (false, false);
+    }
+
+    fn ppu_write(&mut self, address: u16, _data: u8) -> bool {
+        if address <= 0x1FFFu16 {
+            return true;
+        }
+        return false;
+    }
+
+    fn clock_cpu(&mut self) {
+        self.audio.clock_cpu();
+
+        if self.irq_enable {
+            if self.irq_cycle_mode {
+                self.clock_irq_counter();
+            } else {
+                // Scanline mode: the prescaler divides the CPU clock by 113.667
+                self.irq_prescaler -= 3;
+                if self.irq_prescaler <= 0 {
+                    self.irq_prescaler += 341;
+                    self.clock_irq_counter();
+                }
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
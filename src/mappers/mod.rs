@@ -1,6 +1,8 @@
 mod mapper0;
 mod mapper2;
 mod mapper4;
+mod mapper24;
+pub mod nsf;
 
 use crate::ines_loader::INesHeaderInfo;
 use std::io::{BufReader, Read};
@@ -8,6 +10,7 @@ use crate::mappers::mapper0::create_mapper0_cartdrige;
 use crate::cartdrige::Cartdrige;
 use crate::mappers::mapper2::create_mapper2_cartdrige;
 use crate::mappers::mapper4::create_mapper4_cartdrige;
+use crate::mappers::mapper24::create_mapper24_cartdrige;
 
 pub fn create_cartdrige<T: Read>(header: INesHeaderInfo, mut reader: BufReader<T>) -> Box<dyn Cartdrige> {
     println!("Reading cartdrige (header: {:?})", &header);
@@ -15,6 +18,7 @@ pub fn create_cartdrige<T: Read>(header: INesHeaderInfo, mut reader: BufReader<T
         0 => { return create_mapper0_cartdrige(header, &mut reader); }
         2 => { return create_mapper2_cartdrige(header, &mut reader); }
         4 => { return create_mapper4_cartdrige(header, &mut reader); }
+        24 | 26 => { return create_mapper24_cartdrige(header, &mut reader); }
         _ => {
             panic!("Unsupported mapper type: {}", header.mapper_type)
         }
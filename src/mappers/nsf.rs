@@ -0,0 +1,213 @@
+use crate::cartdrige::Cartdrige;
+use crate::ines_loader::{INesHeaderInfo, MirroringMode};
+use crate::nsf_loader::NsfFile;
+use crate::region::Region;
+use crate::apu::expansion::*;
+use crate::apu::expansion::vrc6::Vrc6Audio;
+use crate::apu::expansion::mmc5::Mmc5Audio;
+
+// The driver lives in the otherwise unused $4100 area, it calls INIT then spins forever,
+// PLAY is called from the NMI handler whenever the player decides it's time
+const DRIVER_ADDRESS: u16 = 0x4100;
+pub const DRIVER_IDLE_LOOP: u16 = 0x4107;
+const DRIVER_PLAY: u16 = 0x410A;
+const DRIVER_RTI: u16 = 0x410D;
+
+/// Cartdrige built out of a NSF rip: 8 4KiB banks at $8000-$FFFF switched through
+/// $5FF8-$5FFF, 8KiB of RAM at $6000-$7FFF and a tiny driver routine.
+pub fn create_nsf_cartdrige(nsf: &NsfFile, track: u8, pal: bool) -> Box<dyn Cartdrige> {
+    let info = &nsf.info;
+    if info.load_address < 0x8000 {
+        panic!("Unsupported NSF load address: {:04X}", info.load_address);
+    }
+
+    // Non-bankswitched rips are loaded at their load address and mapped linearly
+    let padding = if info.uses_bankswitching { info.load_address & 0x0FFF } else { info.load_address - 0x8000 };
+    let mut image = vec![0u8; padding as usize];
+    image.extend_from_slice(&nsf.data);
+    let banks: Vec<[u8; 4096]> = image.chunks(4096).map(|chunk| {
+        let mut bank = [0u8; 4096];
+        bank[..chunk.len()].copy_from_slice(chunk);
+        bank
+    }).collect();
+
+    let bank_registers = if info.uses_bankswitching { info.bankswitch_init } else { [0, 1, 2, 3, 4, 5, 6, 7] };
+
+    let init = info.init_address.to_le_bytes();
+    let play = info.play_address.to_le_bytes();
+    let driver = [
+        /* 4100 */ 0xA9, track,                 // LDA #track
+        /* 4102 */ 0xA2, pal as u8,             // LDX #region
+        /* 4104 */ 0x20, init[0], init[1],      // JSR init
+        /* 4107 */ 0x4C, 0x07, 0x41,            // JMP $4107
+        /* 410A */ 0x20, play[0], play[1],      // JSR play
+        /* 410D */ 0x40,                        // RTI
+    ];
+
+    let mut chips: Vec<Box<dyn ExpansionAudio>> = Vec::new();
+    if info.expansion_chips & NSF_CHIP_VRC6 != 0 {
+        chips.push(Box::new(Vrc6Audio::new()));
+    }
+    if info.expansion_chips & NSF_CHIP_MMC5 != 0 {
+        chips.push(Box::new(Mmc5Audio::new()));
+    }
+    let unsupported_chips = [(NSF_CHIP_VRC7, "VRC7"), (NSF_CHIP_FDS, "FDS"), (NSF_CHIP_NAMCO163, "Namco 163"), (NSF_CHIP_SUNSOFT5B, "Sunsoft 5B")];
+    for (bit, name) in unsupported_chips.iter() {
+        if info.expansion_chips & bit != 0 {
+            println!("Warning: {} expansion audio isn't emulated, these parts will be silent", name);
+        }
+    }
+
+    return Box::new(NsfCartdrige {
+        header: INesHeaderInfo {
+            mapper_type: 0,
+            mirroring_mode: MirroringMode::Horizontal,
+            batter_backed_prg_ram: false,
+            trainer_present: false,
+            prg_pages: 0,
+            chr_pages: 0,
+            region: if pal { Region::Pal } else { Region::Ntsc },
+        },
+        banks,
+        bank_registers,
+        uses_bankswitching: info.uses_bankswitching,
+        prg_ram: [0; 8192],
+        driver,
+        expansion: ExpansionAudioChips { chips },
+        uses_mmc5: info.expansion_chips & NSF_CHIP_MMC5 != 0,
+        mmc5_exram: [0; 1024],
+        mmc5_multiplicand: 0,
+        mmc5_multiplier: 0,
+    });
+}
+
+struct NsfCartdrige {
+    header: INesHeaderInfo,
+    banks: Vec<[u8; 4096]>,
+    bank_registers: [u8; 8],
+    uses_bankswitching: bool,
+    prg_ram: [u8; 8192],
+    driver: [u8; 14],
+
+    expansion: ExpansionAudioChips,
+    // MMC5 rips may also use the ExRAM and the hardware multiplier
+    uses_mmc5: bool,
+    mmc5_exram: [u8; 1024],
+    mmc5_multiplicand: u8,
+    mmc5_multiplier: u8,
+}
+
+impl Cartdrige for NsfCartdrige {
+    fn get_info(&self) -> &INesHeaderInfo {
+        return &self.header;
+    }
+
+    fn cpu_read(&mut self, address: u16, data: &mut u8) -> bool {
+        if address >= DRIVER_ADDRESS && address < DRIVER_ADDRESS + self.driver.len() as u16 {
+            *data = self.driver[(address - DRIVER_ADDRESS) as usize];
+            return true;
+        }
+
+        // Vectors point at our driver no matter what the rip has there
+        let vector = match address {
+            0xFFFA | 0xFFFB => Some(DRIVER_PLAY),
+            0xFFFC | 0xFFFD => Some(DRIVER_ADDRESS),
+            0xFFFE | 0xFFFF => Some(DRIVER_RTI),
+            _ => None,
+        };
+        if let Some(vector) = vector {
+            *data = vector.to_le_bytes()[(address & 0x01) as usize];
+            return true;
+        }
+
+        if self.expansion.read_register(address, data) {
+            return true;
+        }
+
+        if self.uses_mmc5 {
+            match address {
+                0x5205 | 0x5206 => {
+                    let product = self.mmc5_multiplicand as u16 * self.mmc5_multiplier as u16;
+                    *data = product.to_le_bytes()[(address - 0x5205) as usize];
+                    return true;
+                }
+                0x5C00..=0x5FF5 => {
+                    *data = self.mmc5_exram[(address - 0x5C00) as usize];
+                    return true;
+                }
+                _ => {}
+            }
+        }
+
+        if address >= 0x6000 && address <= 0x7FFF {
+            *data = self.prg_ram[(address & 0x1FFF) as usize];
+            return true;
+        }
+
+        if address >= 0x8000u16 {
+            let bank = self.bank_registers[((address - 0x8000) >> 12) as usize] as usize;
+            *data = if self.uses_bankswitching {
+                self.banks[bank % self.banks.len()][(address & 0x0FFF) as usize]
+            } else if bank < self.banks.len() {
+                self.banks[bank][(address & 0x0FFF) as usize]
+            } else {
+                0
+            };
+            return true;
+        }
+        return false;
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if address >= 0x5FF8 && address <= 0x5FFF && self.uses_bankswitching {
+            self.bank_registers[(address - 0x5FF8) as usize] = data;
+            return true;
+        }
+
+        if self.expansion.write_register(address, data) {
+            return true;
+        }
+
+        if self.uses_mmc5 {
+            match address {
+                0x5205 => { self.mmc5_multiplicand = data; return true; }
+                0x5206 => { self.mmc5_multiplier = data; return true; }
+                0x5C00..=0x5FF5 => { self.mmc5_exram[(address - 0x5C00) as usize] = data; return true; }
+                _ => {}
+            }
+        }
+
+        if address >= 0x6000 && address <= 0x7FFF {
+            self.prg_ram[(address & 0x1FFF) as usize] = data;
+            return true;
+        }
+
+        if address >= 0x8000u16 {
+            return true;
+        }
+        return false;
+    }
+
+    fn ppu_read(&mut self, address: u16, data: &mut u8) -> (bool, bool) {
+        if address <= 0x1FFFu16 {
+            *data = 0;
+            return (true, false);
+        }
+        return (false, false);
+    }
+
+    fn ppu_write(&mut self, address: u16, _data: u8) -> bool {
+        if address <= 0x1FFFu16 {
+            return true;
+        }
+        return false;
+    }
+
+    fn clock_cpu(&mut self) {
+        self.expansion.clock_cpu();
+    }
+
+    fn expansion_audio_output(&self) -> f32 {
+        self.expansion.output()
+    }
+}
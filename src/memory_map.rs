@@ -0,0 +1,95 @@
+/// What sits behind an address on the CPU bus
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BusDevice {
+    CpuRam,
+    PpuRegisters,
+    ApuRegisters,
+    /// $4015 reads, handled inside the CPU so they don't drive the data bus
+    ApuStatus,
+    OamDma,
+    Controllers,
+    Cartdrige,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryRegion {
+    pub start: u16,
+    /// Inclusive
+    pub end: u16,
+    /// Applied to the address before handing it to the device, that's how mirrors are done
+    pub mirror_mask: u16,
+    pub device: BusDevice,
+}
+
+impl MemoryRegion {
+    pub fn new(start: u16, end: u16, mirror_mask: u16, device: BusDevice) -> Self {
+        Self {
+            start,
+            end,
+            mirror_mask,
+            device,
+        }
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        address >= self.start && address <= self.end
+    }
+}
+
+/// CPU address space layout. Reads and writes have their own tables since some registers
+/// only exist one way. Addresses covered by no region are open bus.
+pub struct MemoryMap {
+    read_regions: Vec<MemoryRegion>,
+    write_regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        let read_regions = vec![
+            MemoryRegion::new(0x0000, 0x1FFF, 0x07FF, BusDevice::CpuRam),
+            MemoryRegion::new(0x2000, 0x3FFF, 0x0007, BusDevice::PpuRegisters),
+            MemoryRegion::new(0x4015, 0x4015, 0xFFFF, BusDevice::ApuStatus),
+            MemoryRegion::new(0x4016, 0x4017, 0xFFFF, BusDevice::Controllers),
+        ];
+        let write_regions = vec![
+            MemoryRegion::new(0x0000, 0x1FFF, 0x07FF, BusDevice::CpuRam),
+            MemoryRegion::new(0x2000, 0x3FFF, 0x0007, BusDevice::PpuRegisters),
+            MemoryRegion::new(0x4000, 0x4013, 0xFFFF, BusDevice::ApuRegisters),
+            MemoryRegion::new(0x4014, 0x4014, 0xFFFF, BusDevice::OamDma),
+            MemoryRegion::new(0x4015, 0x4015, 0xFFFF, BusDevice::ApuRegisters),
+            MemoryRegion::new(0x4016, 0x4016, 0xFFFF, BusDevice::Controllers),
+            MemoryRegion::new(0x4017, 0x4017, 0xFFFF, BusDevice::ApuRegisters),
+        ];
+        Self {
+            read_regions,
+            write_regions,
+        }
+    }
+
+    /// Regions mapped later take precedence over the existing ones
+    pub fn map_read(&mut self, region: MemoryRegion) {
+        self.read_regions.insert(0, region);
+    }
+
+    pub fn map_write(&mut self, region: MemoryRegion) {
+        self.write_regions.insert(0, region);
+    }
+
+    pub fn unmap_device(&mut self, device: BusDevice) {
+        self.read_regions.retain(|r| r.device != device);
+        self.write_regions.retain(|r| r.device != device);
+    }
+
+    /// The device and the address as it sees it
+    pub fn lookup_read(&self, address: u16) -> Option<(BusDevice, u16)> {
+        lookup(&self.read_regions, address)
+    }
+
+    pub fn lookup_write(&self, address: u16) -> Option<(BusDevice, u16)> {
+        lookup(&self.write_regions, address)
+    }
+}
+
+fn lookup(regions: &[MemoryRegion], address: u16) -> Option<(BusDevice, u16)> {
+    regions.iter().find(|r| r.contains(address)).map(|r| (r.device, address & r.mirror_mask))
+}
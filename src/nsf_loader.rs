@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::Read;
+
+const NSF_MAGIC_BYTES: &'static [u8] = b"NESM\x1a";
+const NSFE_MAGIC_BYTES: &'static [u8] = b"NSFE";
+
+/// Everything we know about a NSF/NSFe rip
+#[derive(Debug, Clone)]
+pub struct NsfInfo {
+    pub total_songs: u8,
+    /// 0-based
+    pub starting_song: u8,
+
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+
+    /// Microseconds between two PLAY calls
+    pub play_speed_ntsc: u16,
+    pub play_speed_pal: u16,
+
+    pub bankswitch_init: [u8; 8],
+    pub uses_bankswitching: bool,
+
+    pub pal: bool,
+    pub expansion_chips: u8,
+
+    /// NSFe metadata, indexed by track. Lengths and fades are in milliseconds.
+    pub track_lengths: Vec<Option<u32>>,
+    pub track_fades: Vec<Option<u32>>,
+    pub track_titles: Vec<Option<String>>,
+    pub playlist: Option<Vec<u8>>,
+}
+
+pub struct NsfFile {
+    pub info: NsfInfo,
+    pub data: Vec<u8>,
+}
+
+pub fn is_nsf_file(filename: &str) -> bool {
+    let lowercase = filename.to_lowercase();
+    lowercase.ends_with(".nsf") || lowercase.ends_with(".nsfe")
+}
+
+/// Broken or unsupported rips are rejected with a message instead of crashing halfway through
+pub fn load_nsf_file(filename: &str) -> Result<NsfFile, String> {
+    let mut f = File::open(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut contents = Vec::new();
+    f.read_to_end(&mut contents).map_err(|e| format!("{}: {}", filename, e))?;
+
+    if contents.starts_with(NSF_MAGIC_BYTES) {
+        read_nsf(&contents)
+    } else if contents.starts_with(NSFE_MAGIC_BYTES) {
+        read_nsfe(&contents)
+    } else {
+        Err(String::from("header doesn't match"))
+    }
+}
+
+fn read_nsf(contents: &[u8]) -> Result<NsfFile, String> {
+    if contents.len() < 0x80 {
+        return Err(String::from("NSF file is too short"));
+    }
+
+    let mut bankswitch_init = [0u8; 8];
+    bankswitch_init.copy_from_slice(&contents[0x70..0x78]);
+    let total_songs = contents[0x06];
+    if total_songs == 0 {
+        return Err(String::from("NSF file has no songs"));
+    }
+    let starting_song = contents[0x07].saturating_sub(1);
+
+    let info = NsfInfo {
+        total_songs,
+        starting_song: if starting_song < total_songs { starting_song } else { 0 },
+
+        load_address: read_u16(contents, 0x08)?,
+        init_address: read_u16(contents, 0x0A)?,
+        play_address: read_u16(contents, 0x0C)?,
+
+        name: read_string(&contents[0x0E..0x2E]),
+        artist: read_string(&contents[0x2E..0x4E]),
+        copyright: read_string(&contents[0x4E..0x6E]),
+
+        play_speed_ntsc: read_u16(contents, 0x6E)?,
+        play_speed_pal: read_u16(contents, 0x78)?,
+
+        bankswitch_init,
+        uses_bankswitching: bankswitch_init.iter().any(|b| *b != 0),
+
+        pal: (contents[0x7A] & 0x03) == 0x01,
+        expansion_chips: contents[0x7B],
+
+        track_lengths: vec![None; total_songs as usize],
+        track_fades: vec![None; total_songs as usize],
+        track_titles: vec![None; total_songs as usize],
+        playlist: None,
+    };
+
+    let data = contents[0x80..].to_vec();
+    check_program(&info, &data)?;
+    return Ok(NsfFile {
+        info,
+        data,
+    });
+}
+
+// NSFe is a chunked format: a 4 bytes length, a 4 bytes id, then the chunk data.
+// Chunks whose id starts with an uppercase letter are mandatory to understand.
+fn read_nsfe(contents: &[u8]) -> Result<NsfFile, String> {
+    let mut info = NsfInfo {
+        total_songs: 1,
+        starting_song: 0,
+        load_address: 0,
+        init_address: 0,
+        play_address: 0,
+        name: String::new(),
+        artist: String::new(),
+        copyright: String::new(),
+        play_speed_ntsc: 16639,
+        play_speed_pal: 19997,
+        bankswitch_init: [0; 8],
+        uses_bankswitching: false,
+        pal: false,
+        expansion_chips: 0,
+        track_lengths: Vec::new(),
+        track_fades: Vec::new(),
+        track_titles: Vec::new(),
+        playlist: None,
+    };
+    let mut data = Vec::new();
+    let mut has_info = false;
+
+    let mut at = NSFE_MAGIC_BYTES.len();
+    while at + 8 <= contents.len() {
+        let length = u32::from_le_bytes([contents[at], contents[at + 1], contents[at + 2], contents[at + 3]]) as usize;
+        let id = &contents[at + 4..at + 8];
+        let chunk = &contents[(at + 8).min(contents.len())..(at + 8 + length).min(contents.len())];
+        at += 8 + length;
+
+        match id {
+            b"INFO" => {
+                if chunk.len() < 0x08 {
+                    return Err(String::from("NSFe INFO chunk is too short"));
+                }
+                has_info = true;
+                info.load_address = read_u16(chunk, 0x00)?;
+                info.init_address = read_u16(chunk, 0x02)?;
+                info.play_address = read_u16(chunk, 0x04)?;
+                info.pal = (chunk[0x06] & 0x03) == 0x01;
+                info.expansion_chips = chunk[0x07];
+                if chunk.len() > 0x08 {
+                    info.total_songs = chunk[0x08];
+                }
+                if chunk.len() > 0x09 {
+                    info.starting_song = chunk[0x09];
+                }
+            }
+            b"DATA" => {
+                data = chunk.to_vec();
+            }
+            b"BANK" => {
+                for (i, bank) in chunk.iter().take(8).enumerate() {
+                    info.bankswitch_init[i] = *bank;
+                }
+                info.uses_bankswitching = true;
+            }
+            b"RATE" => {
+                info.play_speed_ntsc = read_u16(chunk, 0x00)?;
+                if chunk.len() >= 4 {
+                    info.play_speed_pal = read_u16(chunk, 0x02)?;
+                }
+            }
+            b"auth" => {
+                let mut strings = chunk.split(|b| *b == 0).map(read_string);
+                info.name = strings.next().unwrap_or_default();
+                info.artist = strings.next().unwrap_or_default();
+                info.copyright = strings.next().unwrap_or_default();
+            }
+            b"plst" => {
+                info.playlist = Some(chunk.to_vec());
+            }
+            b"time" => {
+                info.track_lengths = chunk.chunks(4).map(read_milliseconds).collect();
+            }
+            b"fade" => {
+                info.track_fades = chunk.chunks(4).map(read_milliseconds).collect();
+            }
+            b"tlbl" => {
+                info.track_titles = chunk.split(|b| *b == 0).map(|s| Some(read_string(s))).collect();
+            }
+            b"NEND" => {
+                break;
+            }
+            _ => {
+                if id[0].is_ascii_uppercase() {
+                    return Err(format!("Unsupported NSFe chunk: {}", String::from_utf8_lossy(id)));
+                }
+            }
+        }
+    }
+
+    if !has_info {
+        return Err(String::from("NSFe file has no INFO chunk"));
+    }
+    if data.is_empty() {
+        return Err(String::from("NSFe file has no DATA chunk"));
+    }
+    check_program(&info, &data)?;
+    if info.total_songs == 0 {
+        return Err(String::from("NSFe file has no songs"));
+    }
+    if info.starting_song >= info.total_songs {
+        info.starting_song = 0;
+    }
+
+    let total_songs = info.total_songs as usize;
+    info.track_lengths.resize(total_songs, None);
+    info.track_fades.resize(total_songs, None);
+    info.track_titles.resize(total_songs, None);
+
+    return Ok(NsfFile {
+        info,
+        data,
+    });
+}
+
+/// The cartdrige maps the program at $8000-$FFFF, there has to be something to map
+fn check_program(info: &NsfInfo, data: &[u8]) -> Result<(), String> {
+    if info.load_address < 0x8000 {
+        return Err(format!("Unsupported NSF load address: {:04X}", info.load_address));
+    }
+    if data.is_empty() {
+        return Err(String::from("NSF file has no program data"));
+    }
+    return Ok(());
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+    match bytes.get(at..at + 2) {
+        Some(word) => Ok((word[0] as u16) | ((word[1] as u16) << 8)),
+        None => Err(format!("NSF data ends before offset {:#X}", at + 1)),
+    }
+}
+
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+// NSFe uses -1 for "use the player's default"
+fn read_milliseconds(bytes: &[u8]) -> Option<u32> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if value < 0 { None } else { Some(value as u32) }
+}
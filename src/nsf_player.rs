@@ -0,0 +1,139 @@
+use crate::bus::Bus;
+use crate::nsf_loader::{NsfFile, NsfInfo};
+use crate::mappers::nsf::{create_nsf_cartdrige, DRIVER_IDLE_LOOP};
+use crate::region::Region;
+
+// Used when the rip doesn't tell us how long its tracks are
+pub const DEFAULT_TRACK_LENGTH_MS: u32 = 180_000;
+pub const DEFAULT_FADE_MS: u32 = 8_000;
+
+/// Drives the CPU through a NSF rip: calls INIT when starting a track, then PLAY at the
+/// rate requested by the header, and fades out once the track length is reached.
+pub struct NsfPlayer {
+    nsf: NsfFile,
+    track: u8,
+    region: Region,
+
+    play_period: u64,
+    cycles_until_play: u64,
+    elapsed_cycles: u64,
+
+    default_length_ms: u32,
+    default_fade_ms: u32,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: NsfFile, region: Region, default_length_ms: u32, default_fade_ms: u32) -> Self {
+        let play_speed = match region {
+            Region::Pal => if nsf.info.play_speed_pal == 0 { 19997 } else { nsf.info.play_speed_pal },
+            _ => if nsf.info.play_speed_ntsc == 0 { 16639 } else { nsf.info.play_speed_ntsc },
+        };
+        let play_period = (region.cpu_clock_rate() * play_speed as f64 / 1_000_000.0) as u64;
+        let track = nsf.info.starting_song;
+
+        Self {
+            nsf,
+            track,
+            region,
+
+            play_period,
+            cycles_until_play: play_period,
+            elapsed_cycles: 0,
+
+            default_length_ms,
+            default_fade_ms,
+        }
+    }
+
+    pub fn info(&self) -> &NsfInfo {
+        &self.nsf.info
+    }
+
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    /// The NSFe playlist, without the tracks the rip doesn't have, or every track when there is none
+    pub fn track_order(&self) -> Vec<u8> {
+        let total_songs = self.nsf.info.total_songs;
+        let playlist: Vec<u8> = match &self.nsf.info.playlist {
+            Some(playlist) => playlist.iter().cloned().filter(|t| *t < total_songs).collect(),
+            None => Vec::new(),
+        };
+        if playlist.is_empty() {
+            return (0..total_songs).collect();
+        }
+        return playlist;
+    }
+
+    pub fn track_length_ms(&self) -> u32 {
+        self.nsf.info.track_lengths.get(self.track as usize).cloned().flatten().unwrap_or(self.default_length_ms)
+    }
+
+    pub fn track_fade_ms(&self) -> u32 {
+        self.nsf.info.track_fades.get(self.track as usize).cloned().flatten().unwrap_or(self.default_fade_ms)
+    }
+
+    pub fn start_track(&mut self, nes: &mut Bus, track: u8) {
+        self.track = track;
+        self.elapsed_cycles = 0;
+        self.cycles_until_play = self.play_period;
+
+        nes.load_cartdrige(create_nsf_cartdrige(&self.nsf, track, self.region == Region::Pal));
+        nes.set_region(self.region);
+        nes.reset();
+
+        // Same initial state as a real NSF player would give us
+        *nes.cpu_ram.borrow_mut() = [0; 2048];
+        for address in 0x4000..=0x4013 {
+            nes.cpu_write(address, 0x00);
+        }
+        nes.cpu_write(0x4015, 0x0F);
+        nes.cpu_write(0x4017, 0x40);
+        nes.apu.borrow_mut().set_master_volume(1.0);
+
+        let title = self.nsf.info.track_titles.get(track as usize).cloned().flatten().unwrap_or_default();
+        println!("Playing track {}/{} {}", track + 1, self.nsf.info.total_songs, title);
+    }
+
+    /// Runs one video frame worth of emulation
+    pub fn run_frame(&mut self, nes: &mut Bus) {
+        while !nes.ppu.borrow().frame_complete {
+            if nes.is_cpu_cycle_due() {
+                self.clock_cpu(nes);
+            }
+            nes.clock();
+        }
+        nes.apu.borrow_mut().frame_done();
+        nes.ppu.borrow_mut().frame_complete = false;
+
+        let elapsed_ms = self.elapsed_ms();
+        let length_ms = self.track_length_ms();
+        let fade_ms = self.track_fade_ms();
+        if elapsed_ms > length_ms {
+            let fade = if fade_ms == 0 { 0.0 } else { 1.0 - (elapsed_ms - length_ms) as f32 / fade_ms as f32 };
+            nes.apu.borrow_mut().set_master_volume(if fade < 0.0 { 0.0 } else { fade });
+        }
+    }
+
+    pub fn is_track_finished(&self) -> bool {
+        self.elapsed_ms() >= self.track_length_ms() + self.track_fade_ms()
+    }
+
+    fn elapsed_ms(&self) -> u32 {
+        (self.elapsed_cycles as f64 * 1000.0 / self.region.cpu_clock_rate()) as u32
+    }
+
+    fn clock_cpu(&mut self, nes: &Bus) {
+        self.elapsed_cycles += 1;
+        if self.cycles_until_play > 0 {
+            self.cycles_until_play -= 1;
+        }
+
+        // If the previous PLAY (or INIT) is still running, wait for it to return
+        if self.cycles_until_play == 0 && nes.cpu.borrow().pc == DRIVER_IDLE_LOOP {
+            nes.cpu.borrow_mut().nmi(nes);
+            self.cycles_until_play = self.play_period;
+        }
+    }
+}
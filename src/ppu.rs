@@ -1,7 +1,9 @@
 use crate::bus::Bus;
 use crate::main;
 use crate::ines_loader::MirroringMode;
-use crate::ppu::palette::get_colour_from_palette_ram;
+use crate::region::Region;
+use crate::ppu::palette::get_pixel_from_palette_ram;
+use crate::ppu::event_log::PpuEventKind;
 use std::rc::Rc;
 use std::fmt::Debug;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -10,8 +12,13 @@ use std::borrow::BorrowMut;
 pub mod main_window;
 pub mod patterns_debug_viewer;
 pub mod nametables_debug_viewer;
-mod palette;
-mod window_common;
+pub mod palette;
+pub mod window_common;
+pub mod ntsc_filter;
+pub mod frame_renderer;
+pub mod event_log;
+pub mod event_debug_viewer;
+pub mod oam_debug_viewer;
 
 bf!(Status[u8] {
     unused: 0:4,
@@ -58,6 +65,21 @@ bf!(OAMEntry[u32] {
     x: 24:31,
 });
 
+// Where the sprite evaluation (dots 65-256) is at, following the steps from the nesdev wiki
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum SpriteEvaluationState {
+    /// Looking at Y coordinates, copying them into secondary OAM
+    Scanning,
+    /// Copying the remaining bytes of an in-range sprite
+    Copying(u8),
+    /// Secondary OAM is full, looking for a 9th sprite with the diagonal scan bug
+    Overflow,
+    /// Found one, reading its remaining bytes without copying them anywhere
+    OverflowCopying(u8),
+    /// Went through all of OAM, just keeps incrementing n until hblank
+    Done,
+}
+
 pub struct Ppu where {
     nametables: [[u8; 1024]; 2],
     palette: [u8; 32],
@@ -79,6 +101,21 @@ pub struct Ppu where {
 
     scanline: i16,
     cycle: i16,
+    odd_frame: bool,
+    frame_count: u64,
+    region: Region,
+    /// Colour subcarrier phase of the current dot, the PPU outputs 8 of the 12 samples of a cycle per dot
+    burst_phase: u8,
+    frame_burst_phase: u8,
+    /// Picture being drawn, handed over to the output at vblank
+    frame_buffer: Vec<u16>,
+
+    // Open bus: the data bus between the CPU and the PPU holds the last value put on it,
+    // each bit slowly fades back to 0 unless refreshed
+    io_latch: u8,
+    io_latch_refresh_frame: [u64; 8],
+    // Reading $2002 right before vblank starts prevents the flag from being set that frame
+    suppress_vblank: bool,
 
     bg_next_tile_id: u8,
     bg_next_tile_attrib: u8,
@@ -90,6 +127,13 @@ pub struct Ppu where {
     bg_shifter_attrib_hi: u16,
 
     oam_addr: u8,
+    secondary_oam: [u8; 32],
+    secondary_oam_index: u8,
+    sprite_evaluation_state: SpriteEvaluationState,
+    sprite_evaluation_latch: u8,
+    sprite_evaluation_found: u8,
+    sprite_evaluation_first: bool,
+    sprite_zero_next_line: bool,
     scanline_sprites: [OAMEntry; 8],
     scanline_sprites_count: u8,
     sprite_shifter_pattern_lo: [u8; 8],
@@ -100,11 +144,11 @@ pub struct Ppu where {
     pub send_nmi: bool,
     pub send_irq: bool,
 
-    output: Rc<dyn PpuOutput>,
+    output: Rc<dyn FrameOutput>,
 }
 
 impl Ppu {
-    pub fn new(output: Rc<dyn PpuOutput>) -> Self {
+    pub fn new(output: Rc<dyn FrameOutput>) -> Self {
         return Ppu {
             nametables: [[0u8; 1024]; 2],
             palette: [0; 32],
@@ -123,6 +167,16 @@ impl Ppu {
             ppu_data_buffer: 0,
             scanline: 0,
             cycle: 0,
+            odd_frame: false,
+            frame_count: 0,
+            region: Region::Ntsc,
+            burst_phase: 0,
+            frame_burst_phase: 0,
+            frame_buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+
+            io_latch: 0,
+            io_latch_refresh_frame: [0; 8],
+            suppress_vblank: false,
             bg_next_tile_id: 0,
             bg_next_tile_attrib: 0,
             bg_next_tile_lsb: 0,
@@ -133,6 +187,13 @@ impl Ppu {
             bg_shifter_attrib_hi: 0,
 
             oam_addr: 0,
+            secondary_oam: [0xFF; 32],
+            secondary_oam_index: 0,
+            sprite_evaluation_state: SpriteEvaluationState::Done,
+            sprite_evaluation_latch: 0,
+            sprite_evaluation_found: 0,
+            sprite_evaluation_first: false,
+            sprite_zero_next_line: false,
             scanline_sprites: [OAMEntry::new(0); 8],
             scanline_sprites_count: 0,
             sprite_shifter_pattern_lo: [0; 8],
@@ -147,6 +208,24 @@ impl Ppu {
         };
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Scanline (-1 for the pre-render one) and dot being drawn
+    pub fn position(&self) -> (i16, i16) {
+        return (self.scanline, self.cycle);
+    }
+
+    /// Last complete frame, only meaningful between frames (ie after `frame_complete`)
+    pub fn frame(&self) -> Frame<'_> {
+        return Frame {
+            pixels: &self.frame_buffer,
+            burst_phase: self.frame_burst_phase,
+            number: self.frame_count.saturating_sub(1),
+        };
+    }
+
     pub fn borrow_oam_raw(&mut self) -> &mut [u8] {
         unsafe {
             let slice = self.oam.borrow_mut();
@@ -154,60 +233,128 @@ impl Ppu {
         }
     }
 
-    pub fn read_ppu_register(&mut self, bus: &Bus, address: u16, read_only: bool) -> u8 {
-        let mut data = 0u8;
-        match address {
-            0x0000 => { // Control
-                if read_only {
-                    data = self.control.val;
-                }
+    // Bits decay after around 600ms
+    fn io_latch_value(&mut self) -> u8 {
+        let decay_frames = (self.region.frame_rate() * 0.6) as u64;
+        for bit in 0..8 {
+            if self.frame_count - self.io_latch_refresh_frame[bit] > decay_frames {
+                self.io_latch &= !(1 << bit);
             }
-            0x0001 => { // Mask
-                if read_only {
-                    data = self.mask.val;
-                }
+        }
+        self.io_latch
+    }
+
+    // Only the bits in mask are driven, the others keep their old value
+    fn refresh_io_latch(&mut self, data: u8, mask: u8) {
+        self.io_latch_value();
+        self.io_latch = (self.io_latch & !mask) | (data & mask);
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.io_latch_refresh_frame[bit] = self.frame_count;
             }
+        }
+    }
+
+    /// On the pre-render and visible lines with either layer enabled
+    fn is_rendering(&self) -> bool {
+        (self.mask.render_background() | self.mask.render_sprites()) != 0 && self.scanline < 240
+    }
+
+    // $2007 accesses move v along, while rendering they instead trigger both scroll increments
+    fn increment_vram_address(&mut self) {
+        if self.is_rendering() {
+            self.increment_scroll_x();
+            self.increment_scroll_y();
+        } else {
+            self.vram_addr.val = (self.vram_addr.val + (if self.control.increment_mode() == 1 { 32 } else { 1 })) & 0x7FFF;
+        }
+    }
+
+    pub fn read_ppu_register(&mut self, bus: &Bus, address: u16, read_only: bool) -> u8 {
+        // Debug reads peek at the registers without side effects
+        if read_only {
+            let oam_addr = self.oam_addr as usize;
+            return match address {
+                0x0000 => self.control.val,
+                0x0001 => self.mask.val,
+                0x0002 => self.status.val,
+                0x0004 => self.borrow_oam_raw()[oam_addr],
+                0x0007 => self.ppu_data_buffer,
+                _ => self.io_latch,
+            };
+        }
+
+        let data: u8;
+        match address {
             0x0002 => { // Status
-                if read_only {
-                    data = self.status.val;
-                } else {
-                    data = (self.status.val & 0xE0) | (self.ppu_data_buffer & 0x1F);
-                    self.status.set_vertical_blank(0);
-                    self.address_latch = 0;
+                // The cycle counter already points at the next dot
+                if self.scanline == self.region.vblank_scanline() {
+                    if self.cycle == 1 {
+                        // One dot before the flag gets set: reads as clear, and it won't get set
+                        self.suppress_vblank = true;
+                    } else if self.cycle == 2 || self.cycle == 3 {
+                        // Just set: reads as set, but the NMI doesn't happen
+                        self.send_nmi = false;
+                    }
                 }
-            }
-            0x0003 => { // OAM address
+                data = (self.status.val & 0xE0) | (self.io_latch_value() & 0x1F);
+                self.refresh_io_latch(data, 0xE0);
+                self.status.set_vertical_blank(0);
+                self.address_latch = 0;
             }
             0x0004 => { // OAM data
                 let addr = self.oam_addr as usize;
-                data = self.borrow_oam_raw()[addr];
-            }
-            0x0005 => { // Scroll
-            }
-            0x0006 => { // PPU Address
+                let clearing_secondary_oam = self.is_rendering() && self.scanline >= 0 && self.cycle >= 1 && self.cycle <= 64;
+                let mut value = if clearing_secondary_oam {
+                    0xFF
+                } else {
+                    self.borrow_oam_raw()[addr]
+                };
+                if !clearing_secondary_oam && addr & 0x03 == 2 {
+                    // These attribute bits don't exist
+                    value &= 0xE3;
+                }
+                data = value;
+                self.refresh_io_latch(data, 0xFF);
             }
             0x0007 => { // PPU data
-                if !read_only {
+                let address = self.vram_addr.val & 0x3FFF;
+                if address >= 0x3F00 {
+                    // Palette reads are immediate, the buffer gets the nametable byte underneath.
+                    // Palette entries are 6 bits, the top 2 come from the open bus.
+                    let palette_value = self.ppu_read(bus, address, false);
+                    self.ppu_data_buffer = self.ppu_read(bus, address - 0x1000, false);
+                    data = (palette_value & 0x3F) | (self.io_latch_value() & 0xC0);
+                    self.refresh_io_latch(data, 0x3F);
+                } else {
                     data = self.ppu_data_buffer;
-                    self.ppu_data_buffer = self.ppu_read(bus, self.vram_addr.val, read_only);
-                    if self.vram_addr.val >= 0x3F00 {
-                        data = self.ppu_data_buffer;
-                    }
-                    self.vram_addr.val = self.vram_addr.val + (if self.control.increment_mode() == 1 { 32 } else { 1 });
+                    self.ppu_data_buffer = self.ppu_read(bus, address, false);
+                    self.refresh_io_latch(data, 0xFF);
                 }
+                self.increment_vram_address();
+            }
+            // Write-only registers
+            _ => {
+                data = self.io_latch_value();
             }
-            _ => panic!("Unreachable")
         }
 
         return data;
     }
 
     pub fn write_ppu_register(&mut self, bus: &Bus, address: u16, data: u8) {
+        self.refresh_io_latch(data, 0xFF);
         match address {
             0x0000 => { // Control
+                let nmi_was_enabled = self.control.enable_nmi() != 0;
                 self.control.val = data;
                 self.tram_addr.set_nametable_x(self.control.nametable_x() as u16);
                 self.tram_addr.set_nametable_y(self.control.nametable_y() as u16);
+
+                // Enabling NMIs while the vblank flag is up triggers one straight away
+                if !nmi_was_enabled && self.control.enable_nmi() != 0 && self.status.vertical_blank() != 0 {
+                    self.send_nmi = true;
+                }
             }
             0x0001 => { // Mask
                 self.mask.val = data;
@@ -218,8 +365,14 @@ impl Ppu {
                 self.oam_addr = data;
             }
             0x0004 => { // OAM data
-                let addr = self.oam_addr as usize;
-                self.borrow_oam_raw()[addr] = data;
+                if self.is_rendering() {
+                    // No write happens, but the high 6 bits of OAMADDR get bumped
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    let addr = self.oam_addr as usize;
+                    self.borrow_oam_raw()[addr] = data;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
             }
             0x0005 => { // Scroll
                 if self.address_latch == 0 {
@@ -244,7 +397,7 @@ impl Ppu {
             }
             0x0007 => { // PPU data
                 self.ppu_write(bus, self.vram_addr.val, data);
-                self.vram_addr.val += (if self.control.increment_mode() == 1 { 32 } else { 1 });
+                self.increment_vram_address();
             }
             _ => panic!("Unreachable")
         }
@@ -273,6 +426,8 @@ impl Ppu {
                 MirroringMode::Horizontal => { quadrant / 2 }
                 MirroringMode::Vertical => { quadrant % 2 }
                 MirroringMode::FourScreen => { quadrant }
+                MirroringMode::OneScreenLower => { 0 }
+                MirroringMode::OneScreenUpper => { 1 }
             };
 
             data = self.nametables[tlb_bank as usize][(address & 0x03FF) as usize];
@@ -304,6 +459,8 @@ impl Ppu {
                 MirroringMode::Horizontal => { quadrant / 2 }
                 MirroringMode::Vertical => { quadrant % 2 }
                 MirroringMode::FourScreen => { quadrant }
+                MirroringMode::OneScreenLower => { 0 }
+                MirroringMode::OneScreenUpper => { 1 }
             };
 
             self.nametables[tlb_bank as usize][(address & 0x03FF) as usize] = data;
@@ -405,9 +562,107 @@ impl Ppu {
         }
     }
 
+    fn is_sprite_in_range(&self, y: u8) -> bool {
+        let diff = self.scanline - y as i16;
+        diff >= 0 && diff < (if self.control.sprite_size() != 0 { 16 } else { 8 })
+    }
+
+    // Dots 1-64 clear secondary OAM, dots 65-256 evaluate sprites for the next line: odd dots
+    // read primary OAM at OAMADDR, even dots write into secondary OAM.
+    fn clock_sprite_evaluation(&mut self, bus: &Bus) {
+        if self.cycle <= 64 {
+            if self.cycle % 2 == 0 {
+                self.secondary_oam[(self.cycle / 2 - 1) as usize] = 0xFF;
+            }
+            return;
+        }
+
+        if self.cycle == 65 {
+            self.secondary_oam_index = 0;
+            self.sprite_evaluation_found = 0;
+            self.sprite_evaluation_state = SpriteEvaluationState::Scanning;
+            self.sprite_evaluation_first = true;
+            self.sprite_zero_next_line = false;
+        }
+
+        if self.cycle % 2 == 1 {
+            let addr = self.oam_addr as usize;
+            self.sprite_evaluation_latch = self.borrow_oam_raw()[addr];
+            return;
+        }
+
+        let data = self.sprite_evaluation_latch;
+        match self.sprite_evaluation_state {
+            SpriteEvaluationState::Scanning => {
+                self.secondary_oam[self.secondary_oam_index as usize] = data;
+                if self.is_sprite_in_range(data) {
+                    if self.sprite_evaluation_first {
+                        self.sprite_zero_next_line = true;
+                    }
+                    self.secondary_oam_index += 1;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                    self.sprite_evaluation_state = SpriteEvaluationState::Copying(3);
+                } else {
+                    let (next, wrapped) = self.oam_addr.overflowing_add(4);
+                    self.oam_addr = next;
+                    if wrapped {
+                        self.sprite_evaluation_state = SpriteEvaluationState::Done;
+                    }
+                }
+                self.sprite_evaluation_first = false;
+            }
+            SpriteEvaluationState::Copying(remaining) => {
+                self.secondary_oam[self.secondary_oam_index as usize] = data;
+                self.secondary_oam_index += 1;
+                let (next, wrapped) = self.oam_addr.overflowing_add(1);
+                self.oam_addr = next;
+
+                if remaining > 1 {
+                    self.sprite_evaluation_state = SpriteEvaluationState::Copying(remaining - 1);
+                } else {
+                    self.sprite_evaluation_found += 1;
+                    self.sprite_evaluation_state = if wrapped {
+                        SpriteEvaluationState::Done
+                    } else if self.sprite_evaluation_found == 8 {
+                        SpriteEvaluationState::Overflow
+                    } else {
+                        SpriteEvaluationState::Scanning
+                    };
+                }
+            }
+            SpriteEvaluationState::Overflow => {
+                if self.is_sprite_in_range(data) {
+                    if self.status.sprite_overflow() == 0 {
+                        bus.ppu_events.borrow_mut().log(PpuEventKind::SpriteOverflow, self.scanline, self.cycle, 0);
+                    }
+                    self.status.set_sprite_overflow(1);
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                    self.sprite_evaluation_state = SpriteEvaluationState::OverflowCopying(3);
+                } else {
+                    // The hardware bug: both n and m get incremented, m without carrying into n,
+                    // so we end up looking at tile ids, attributes and x positions as if they were Y
+                    let n = (self.oam_addr >> 2) as u16 + 1;
+                    let m = (self.oam_addr.wrapping_add(1)) & 0x03;
+                    self.oam_addr = ((n << 2) as u8) | m;
+                    if n > 63 {
+                        self.sprite_evaluation_state = SpriteEvaluationState::Done;
+                    }
+                }
+            }
+            SpriteEvaluationState::OverflowCopying(remaining) => {
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+                self.sprite_evaluation_state = if remaining > 1 { SpriteEvaluationState::OverflowCopying(remaining - 1) } else { SpriteEvaluationState::Done };
+            }
+            SpriteEvaluationState::Done => {
+                self.oam_addr = self.oam_addr.wrapping_add(4);
+            }
+        }
+    }
+
     pub fn clock(&mut self, bus: &Bus) {
         if self.scanline >= -1 && self.scanline < 240 {
-            if self.scanline == 0 && self.cycle == 0 {
+            let rendering = self.mask.render_background() != 0 || self.mask.render_sprites() != 0;
+            if self.scanline == 0 && self.cycle == 0 && self.odd_frame && rendering && self.region.skips_odd_frame_dot() {
                 // Odd frame cycle skip
                 self.cycle = 1;
             }
@@ -415,6 +670,16 @@ impl Ppu {
             if self.scanline == -1 && self.cycle == 1 {
                 self.status.set_vertical_blank(0);
 
+                // 2C02 quirk: starting to render with OAMADDR >= 8 copies the 8 bytes at
+                // OAMADDR & 0xF8 over the first 8 bytes of OAM
+                if rendering && self.oam_addr >= 8 {
+                    let from = (self.oam_addr & 0xF8) as usize;
+                    let oam = self.borrow_oam_raw();
+                    for i in 0..8 {
+                        oam[i] = oam[from + i];
+                    }
+                }
+
                 self.status.set_sprite_zero_hit(0);
                 self.status.set_sprite_overflow(0);
                 self.sprite_shifter_pattern_lo.iter_mut().for_each(|a| { *a = 0; });
@@ -471,33 +736,27 @@ impl Ppu {
                 self.transfer_address_y();
             }
 
-            // Sprite evaluation
+            // Sprite evaluation, spread over the scanline like the real thing
+            if rendering && self.scanline >= 0 && self.cycle >= 1 && self.cycle <= 256 {
+                self.clock_sprite_evaluation(bus);
+            }
+
             if self.cycle == 257 && self.scanline >= 0 {
-                self.scanline_sprites.iter_mut().for_each(|it| { it.set_y(0xff); });
+                self.scanline_sprites_count = self.secondary_oam_index / 4;
+                for i in 0..self.scanline_sprites_count as usize {
+                    let bytes = &self.secondary_oam[i * 4..i * 4 + 4];
+                    self.scanline_sprites[i] = OAMEntry::new(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+                }
+                self.sprite_zero_selected = self.sprite_zero_next_line;
+            } else if self.cycle == 257 {
+                // Nothing gets evaluated on the pre-render line, so no sprites on line 0
                 self.scanline_sprites_count = 0;
                 self.sprite_zero_selected = false;
+            }
 
-                for i in 0..64 {
-                    if self.scanline_sprites_count == 9 {
-                        break;
-                    }
-
-                    let diff = self.scanline - ((self.oam[i].y() as u16) as i16);
-                    if diff >= 0 && diff < (if self.control.sprite_size() != 0 { 16 } else { 8 }) {
-                        if self.scanline_sprites_count < 8 {
-                            if i == 0 {
-                                self.sprite_zero_selected = true;
-                            }
-
-                            self.scanline_sprites[self.scanline_sprites_count as usize] = self.oam[i];
-
-                            //???
-                            self.scanline_sprites_count += 1;
-                        }
-                    }
-                }
-
-                self.status.set_sprite_overflow((self.scanline_sprites_count > 8) as u8);
+            // OAMADDR gets cleared during the sprite tiles fetches
+            if rendering && self.cycle >= 257 && self.cycle <= 320 {
+                self.oam_addr = 0;
             }
 
             // Sprite shifter population
@@ -566,19 +825,33 @@ impl Ppu {
             // nothing lol
         }
 
-        if self.scanline >= 241 && self.scanline < 261 {
-            if self.scanline == 241 && self.cycle == 1 {
-                self.status.set_vertical_blank(1);
-                if self.control.enable_nmi() != 0 {
-                    self.send_nmi = true;
+        let vblank_scanline = self.region.vblank_scanline();
+        if self.scanline >= vblank_scanline && self.scanline < self.region.scanlines() - 1 {
+            if self.scanline == vblank_scanline && self.cycle == 1 {
+                self.output.present_frame(&Frame {
+                    pixels: &self.frame_buffer,
+                    burst_phase: self.frame_burst_phase,
+                    number: self.frame_count,
+                });
+                if !self.suppress_vblank {
+                    self.status.set_vertical_blank(1);
+                    if self.control.enable_nmi() != 0 {
+                        self.send_nmi = true;
+                    }
                 }
+                self.suppress_vblank = false;
             }
         }
 
+        // Dot 1 outputs the first pixel of the line
+        let x = self.cycle - 1;
+        let visible_pixel = self.scanline >= 0 && self.scanline < 240 && x >= 0 && x < 256;
+
         let mut bg_pixel = 0u8;
         let mut bg_palette = 0u8;
 
-        if self.mask.render_background() != 0 {
+        // Left clipping hides the leftmost 8 pixels of a layer
+        if self.mask.render_background() != 0 && (x >= 8 || self.mask.render_background_left() != 0) {
             let bit_mux = 0x8000 >> self.fine_x;
 
             let p0_pixel = ((self.bg_shifter_pattern_lo & bit_mux) > 0) as u8;
@@ -596,7 +869,7 @@ impl Ppu {
 
         let mut sprite_zero_rendering = false;
 
-        if self.mask.render_sprites() != 0 {
+        if self.mask.render_sprites() != 0 && (x >= 8 || self.mask.render_sprites_left() != 0) {
             for i in 0..self.scanline_sprites_count {
                 let sprite = &self.scanline_sprites[i as usize];
 
@@ -641,30 +914,40 @@ impl Ppu {
                 final_palette = bg_palette;
             }
 
-            if self.sprite_zero_selected && sprite_zero_rendering {
+            // Clipped pixels are already transparent here. The hit never happens on the last
+            // pixel of the line, nor outside of the visible area.
+            if self.sprite_zero_selected && sprite_zero_rendering && visible_pixel && x != 255 {
                 if (self.mask.render_sprites() != 0) && (self.mask.render_background() != 0) {
-                    if !((self.mask.render_background_left() != 0) || (self.mask.render_sprites_left() != 0)) {
-                        if self.cycle >= 9 && self.cycle < 258 {
-                            self.status.set_sprite_zero_hit(1);
-                        }
-                    } else {
-                        if self.cycle >= 1 && self.cycle < 258 {
-                            self.status.set_sprite_zero_hit(1);
-                        }
+                    if self.status.sprite_zero_hit() == 0 {
+                        bus.ppu_events.borrow_mut().log(PpuEventKind::SpriteZeroHit, self.scanline, self.cycle, 0);
                     }
+                    self.status.set_sprite_zero_hit(1);
                 }
             }
         }
 
-        let color = get_colour_from_palette_ram(self, bus, final_palette, final_pixel);
-        self.output.set_pixel((self.cycle - 1) as i32, self.scanline as i32, color);
+        if self.scanline == 0 && self.cycle == 1 {
+            self.frame_burst_phase = self.burst_phase;
+        }
+        let mut emphasis = ((self.mask.val & 0xE0) as u16) << 1;
+        if self.region != Region::Ntsc {
+            // The PAL PPUs swap the red and green emphasis bits
+            emphasis = (emphasis & 0x100) | ((emphasis & 0x40) << 1) | ((emphasis & 0x80) >> 1);
+        }
+        if visible_pixel {
+            let pixel = get_pixel_from_palette_ram(self, bus, final_palette, final_pixel) as u16 | emphasis;
+            self.frame_buffer[self.scanline as usize * FRAME_WIDTH + x as usize] = pixel;
+        }
 
+        self.burst_phase = (self.burst_phase + 8) % 12;
         self.cycle += 1;
         if self.cycle >= 341 {
             self.cycle = 0;
             self.scanline += 1;
-            if self.scanline >= 261 {
+            if self.scanline >= self.region.scanlines() - 1 {
                 self.scanline = -1;
+                self.odd_frame = !self.odd_frame;
+                self.frame_count += 1;
                 self.frame_complete = true;
             }
         }
@@ -694,6 +977,19 @@ impl Ppu {
     }
 }
 
-pub trait PpuOutput {
-    fn set_pixel(&self, x: i32, y: i32, rgb: (u8, u8, u8));
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+/// A complete picture out of the PPU
+pub struct Frame<'a> {
+    /// `FRAME_WIDTH` x `FRAME_HEIGHT` pixels, colour index in bits 0-5 and the emphasis bits of PPUMASK in bits 6-8
+    pub pixels: &'a [u16],
+    /// Colour subcarrier phase at the first pixel, moves around along the odd frame skip (dot crawl)
+    pub burst_phase: u8,
+    pub number: u64,
+}
+
+/// Gets every frame once it's done, at the start of vblank
+pub trait FrameOutput {
+    fn present_frame(&self, frame: &Frame);
 }
\ No newline at end of file
@@ -0,0 +1,145 @@
+use minifb::{Window, WindowOptions, MouseMode};
+use crate::bus::Bus;
+use crate::region::Region;
+use crate::ppu::event_log::{PpuEvent, PpuEventKind};
+use crate::ppu::window_common::{pack, draw_text};
+
+const DOTS: usize = 341;
+/// Every dot is a 2x2 square, events are easier to point at that way
+const DOT_SIZE: usize = 2;
+const INFO_HEIGHT: usize = 60;
+/// How far from the mouse pointer events get listed, in dots
+const HOVER_DISTANCE: i16 = 2;
+const MAX_LISTED_EVENTS: usize = 6;
+
+fn event_color(kind: &PpuEventKind) -> u32 {
+    match kind {
+        PpuEventKind::RegisterWrite(0) => pack(0xFF, 0x40, 0x40),
+        PpuEventKind::RegisterWrite(1) => pack(0xFF, 0xA0, 0x40),
+        PpuEventKind::RegisterWrite(3) | PpuEventKind::RegisterWrite(4) => pack(0xC0, 0x80, 0xFF),
+        PpuEventKind::RegisterWrite(5) => pack(0x40, 0xE0, 0x40),
+        PpuEventKind::RegisterWrite(6) => pack(0x40, 0xA0, 0xFF),
+        PpuEventKind::RegisterWrite(_) => pack(0x40, 0xE0, 0xE0),
+        PpuEventKind::OamDma => pack(0xE0, 0x60, 0xE0),
+        PpuEventKind::MapperWrite(_) => pack(0xFF, 0xFF, 0x40),
+        PpuEventKind::Nmi => pack(0xFF, 0xFF, 0xFF),
+        PpuEventKind::Irq => pack(0xFF, 0x80, 0xC0),
+        PpuEventKind::SpriteZeroHit => pack(0xA0, 0xFF, 0xA0),
+        PpuEventKind::SpriteOverflow => pack(0xFF, 0xC0, 0x80),
+    }
+}
+
+const LEGEND: [(&str, PpuEventKind); 12] = [
+    ("2000", PpuEventKind::RegisterWrite(0)),
+    ("2001", PpuEventKind::RegisterWrite(1)),
+    ("2003/4", PpuEventKind::RegisterWrite(3)),
+    ("2005", PpuEventKind::RegisterWrite(5)),
+    ("2006", PpuEventKind::RegisterWrite(6)),
+    ("2007", PpuEventKind::RegisterWrite(7)),
+    ("4014", PpuEventKind::OamDma),
+    ("MAPPER", PpuEventKind::MapperWrite(0)),
+    ("NMI", PpuEventKind::Nmi),
+    ("IRQ", PpuEventKind::Irq),
+    ("SPR0", PpuEventKind::SpriteZeroHit),
+    ("OVERFLOW", PpuEventKind::SpriteOverflow),
+];
+
+/// Where the register writes, interrupts and sprite flags of the last frame happened, dot by dot.
+/// Hovering the grid lists the events around the pointer.
+pub struct EventDebugWindow {
+    pub window: Window,
+    buffer: Vec<u32>,
+    scanlines: usize,
+}
+
+impl EventDebugWindow {
+    /// The PAL and Dendy frames are taller
+    pub fn new(region: Region) -> Self {
+        let scanlines = region.scanlines() as usize;
+        let width = DOTS * DOT_SIZE;
+        let height = scanlines * DOT_SIZE + INFO_HEIGHT;
+
+        let buffer: Vec<u32> = vec![0; (width * height) as usize];
+        let options = WindowOptions {
+            resize: false,
+            ..WindowOptions::default()
+        };
+        let window = Window::new("PPU events", width as usize, height as usize, options).unwrap_or_else(|e| { panic!("{}", e); });
+        return Self {
+            window,
+            buffer,
+            scanlines,
+        };
+    }
+
+    fn fill_dot(&mut self, dot: usize, row: usize, color: u32) {
+        let width = DOTS * DOT_SIZE;
+        for dy in 0..DOT_SIZE {
+            for dx in 0..DOT_SIZE {
+                self.buffer[(row * DOT_SIZE + dy) * width + dot * DOT_SIZE + dx] = color;
+            }
+        }
+    }
+
+    pub fn update(&mut self, bus: &Bus) {
+        let width = DOTS * DOT_SIZE;
+        let events = bus.ppu_events.borrow();
+        let events = events.last_frame();
+
+        // The pre-render line goes on top, rows are scanline + 1
+        for row in 0..self.scanlines {
+            let scanline = row as i16 - 1;
+            for dot in 0..DOTS {
+                let visible = scanline >= 0 && scanline < 240 && dot >= 1 && dot <= 256;
+                let color = if visible { pack(0x30, 0x30, 0x30) } else { pack(0x18, 0x18, 0x18) };
+                self.fill_dot(dot, row, color);
+            }
+        }
+        for pixel in self.buffer[(self.scanlines * DOT_SIZE * width)..].iter_mut() {
+            *pixel = pack(0x10, 0x10, 0x10);
+        }
+
+        for event in events.iter() {
+            let row = (event.scanline + 1) as usize;
+            let dot = event.cycle as usize;
+            if row < self.scanlines && dot < DOTS {
+                self.fill_dot(dot, row, event_color(&event.kind));
+            }
+        }
+
+        let info_y = self.scanlines * DOT_SIZE + 4;
+        let mut legend_x = 4;
+        for (name, kind) in LEGEND.iter() {
+            for y in info_y..(info_y + 5) {
+                for x in legend_x..(legend_x + 5) {
+                    self.buffer[y * width + x] = event_color(kind);
+                }
+            }
+            draw_text(&mut self.buffer, width, legend_x + 8, info_y, name, pack(0xC0, 0xC0, 0xC0), 1);
+            legend_x += 8 + name.len() * 4 + 12;
+        }
+
+        let mut header = format!("{} EVENTS", events.len());
+        let mut nearby: Vec<&PpuEvent> = Vec::new();
+        if let Some((mouse_x, mouse_y)) = self.window.get_mouse_pos(MouseMode::Discard) {
+            let dot = (mouse_x as usize / DOT_SIZE) as i16;
+            let row = mouse_y as usize / DOT_SIZE;
+            if row < self.scanlines {
+                let scanline = row as i16 - 1;
+                header = format!("{} EVENTS - SCANLINE {} DOT {}", events.len(), scanline, dot);
+                nearby = events.iter()
+                    .filter(|e| e.scanline == scanline && (e.cycle - dot).abs() <= HOVER_DISTANCE)
+                    .take(MAX_LISTED_EVENTS)
+                    .collect();
+            }
+        }
+        draw_text(&mut self.buffer, width, 4, info_y + 12, &header, pack(0xFF, 0xFF, 0xFF), 1);
+        // Two columns of three
+        for (i, event) in nearby.iter().enumerate() {
+            let line = format!("{}:{} {}", event.scanline, event.cycle, event.kind.describe(event.value));
+            draw_text(&mut self.buffer, width, 4 + (i / 3) * 240, info_y + 22 + (i % 3) * 10, &line, event_color(&event.kind), 1);
+        }
+
+        self.window.update_with_buffer(self.buffer.as_slice()).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+/// Something worth seeing on the PPU timeline, for the event viewer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PpuEventKind {
+    /// CPU write to $2000-$2007, the register number
+    RegisterWrite(u8),
+    OamDma,
+    /// CPU write to a mapper register
+    MapperWrite(u16),
+    Nmi,
+    Irq,
+    SpriteZeroHit,
+    SpriteOverflow,
+}
+
+impl PpuEventKind {
+    pub fn describe(&self, value: u8) -> String {
+        match self {
+            PpuEventKind::RegisterWrite(register) => format!("${:04X} = ${:02X}", 0x2000 + *register as u16, value),
+            PpuEventKind::OamDma => format!("$4014 = ${:02X}", value),
+            PpuEventKind::MapperWrite(address) => format!("MAPPER ${:04X} = ${:02X}", address, value),
+            PpuEventKind::Nmi => "NMI".to_string(),
+            PpuEventKind::Irq => "IRQ".to_string(),
+            PpuEventKind::SpriteZeroHit => "SPRITE 0 HIT".to_string(),
+            PpuEventKind::SpriteOverflow => "SPRITE OVERFLOW".to_string(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PpuEvent {
+    pub kind: PpuEventKind,
+    pub scanline: i16,
+    pub cycle: i16,
+    pub value: u8,
+}
+
+/// Collects the events of the frame being emulated, and keeps the ones of the last complete frame.
+/// Stays empty unless enabled, nobody looks at it otherwise.
+pub struct EventLog {
+    enabled: bool,
+    current: Vec<PpuEvent>,
+    last_frame: Vec<PpuEvent>,
+    irq_line: bool,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            current: Vec::new(),
+            last_frame: Vec::new(),
+            irq_line: false,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.enabled;
+    }
+
+    pub fn log(&mut self, kind: PpuEventKind, scanline: i16, cycle: i16, value: u8) {
+        if self.enabled {
+            self.current.push(PpuEvent { kind, scanline, cycle, value });
+        }
+    }
+
+    /// The IRQ line is level triggered, only its rising edges are interesting
+    pub fn log_irq_line(&mut self, asserted: bool, scanline: i16, cycle: i16) {
+        if asserted && !self.irq_line {
+            self.log(PpuEventKind::Irq, scanline, cycle, 0);
+        }
+        self.irq_line = asserted;
+    }
+
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.last_frame);
+        self.current.clear();
+    }
+
+    pub fn last_frame(&self) -> &[PpuEvent] {
+        return &self.last_frame;
+    }
+}
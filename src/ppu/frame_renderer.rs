@@ -0,0 +1,88 @@
+use crate::ppu::{Frame, FRAME_WIDTH, FRAME_HEIGHT};
+use crate::ppu::palette::Palette;
+use crate::ppu::ntsc_filter::{NtscFilter, NTSC_OUTPUT_WIDTH};
+use crate::ppu::window_common::pack;
+use crate::tools::png_writer::write_png;
+
+/// RGB picture, packed the way minifb wants it
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Nearest neighbour upscale, keeps the pixels sharp
+    pub fn scaled(&self, scale: usize) -> Image {
+        let mut image = Image::new(self.width * scale, self.height * scale);
+        for y in 0..image.height {
+            let source_line = &self.pixels[((y / scale) * self.width)..((y / scale + 1) * self.width)];
+            for x in 0..image.width {
+                image.pixels[y * image.width + x] = source_line[x / scale];
+            }
+        }
+        return image;
+    }
+
+    pub fn save_png(&self, filename: &str) {
+        write_png(filename, self.width, self.height, &self.pixels);
+    }
+}
+
+/// Turns PPU frames into pictures, through the palette or the NTSC filter
+pub struct FrameRenderer {
+    palette: Palette,
+    ntsc_filter: Option<NtscFilter>,
+    filtered: Vec<u32>,
+}
+
+impl FrameRenderer {
+    pub fn new(palette: Palette, ntsc_filter: Option<NtscFilter>) -> Self {
+        Self {
+            palette,
+            ntsc_filter,
+            filtered: vec![0; NTSC_OUTPUT_WIDTH * FRAME_HEIGHT],
+        }
+    }
+
+    /// The NTSC filter output is twice as wide, lines get doubled to keep the proportions
+    pub fn output_size(&self) -> (usize, usize) {
+        match self.ntsc_filter {
+            Some(_) => (NTSC_OUTPUT_WIDTH, FRAME_HEIGHT * 2),
+            None => (FRAME_WIDTH, FRAME_HEIGHT),
+        }
+    }
+
+    pub fn create_image(&self) -> Image {
+        let (width, height) = self.output_size();
+        return Image::new(width, height);
+    }
+
+    /// `image` should come from `create_image`
+    pub fn render(&mut self, frame: &Frame, image: &mut Image) {
+        match self.ntsc_filter.as_mut() {
+            Some(filter) => {
+                filter.filter_frame(frame.pixels, frame.burst_phase, &mut self.filtered);
+                for y in 0..FRAME_HEIGHT {
+                    let line = &self.filtered[(y * NTSC_OUTPUT_WIDTH)..((y + 1) * NTSC_OUTPUT_WIDTH)];
+                    image.pixels[(2 * y * NTSC_OUTPUT_WIDTH)..((2 * y + 1) * NTSC_OUTPUT_WIDTH)].copy_from_slice(line);
+                    image.pixels[((2 * y + 1) * NTSC_OUTPUT_WIDTH)..((2 * y + 2) * NTSC_OUTPUT_WIDTH)].copy_from_slice(line);
+                }
+            }
+            None => {
+                for (color, pixel) in image.pixels.iter_mut().zip(frame.pixels.iter()) {
+                    let rgb = self.palette.rgb(*pixel);
+                    *color = pack(rgb.0, rgb.1, rgb.2);
+                }
+            }
+        }
+    }
+}
@@ -1,48 +1,174 @@
-use minifb::{Window, WindowOptions};
+use minifb::{Window, WindowOptions, Scale};
 use crate::ppu::patterns_debug_viewer::PatternsDebugWindow;
 use crate::ppu::window_common::{Color, pack};
-use crate::ppu::PpuOutput;
+use crate::ppu::{FrameOutput, Frame, FRAME_WIDTH, FRAME_HEIGHT};
+use crate::ppu::frame_renderer::{FrameRenderer, Image};
+use crate::ppu::oam_debug_viewer::SpriteBox;
 use std::cell::RefCell;
 
+/// TVs hid about 8 pixels on every side of the picture
+const OVERSCAN: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DisplaySettings {
+    /// Window size in NES pixels, 1 to 6
+    pub scale: usize,
+    /// Stretches the picture to the 8:7 pixel aspect ratio of a NTSC TV
+    pub aspect_correction: bool,
+    pub crop_overscan: bool,
+    /// Borderless window, as big as the screen allows
+    pub fullscreen: bool,
+}
+
+impl DisplaySettings {
+    pub fn new() -> Self {
+        Self {
+            scale: 1,
+            aspect_correction: false,
+            crop_overscan: false,
+            fullscreen: false,
+        }
+    }
+}
+
 pub struct MainWindow {
     pub window: Window,
-    buffer: Vec<u32>,
+    renderer: FrameRenderer,
+    image: Image,
+    /// What the window shows, `image` once cropped and scaled
+    display: Vec<u32>,
+    /// Image column and row every display pixel comes from
+    source_columns: Vec<usize>,
+    source_rows: Vec<usize>,
+    /// Visible part of the frame, in NES pixels
+    crop: usize,
+    visible_width: usize,
+    visible_height: usize,
+    /// Drawn over the picture when set, ie by the OAM viewer
+    sprite_boxes: Vec<SpriteBox>,
+    /// Last frame out of the PPU
+    pixels: Vec<u16>,
+    burst_phase: u8,
+    frame_number: u64,
 }
 
 impl MainWindow {
-    pub fn new() -> Self {
-        let width = 256 as usize;
-        let height = 240 as usize;
+    pub fn new(renderer: FrameRenderer, settings: DisplaySettings) -> Self {
+        let image = renderer.create_image();
 
-        let mut buffer: Vec<u32> = vec![0; (width * height) as usize];
-        let options = WindowOptions {
-            resize: false,
-            ..WindowOptions::default()
+        let (visible_width, visible_height) = if settings.crop_overscan {
+            (FRAME_WIDTH - 2 * OVERSCAN, FRAME_HEIGHT - 2 * OVERSCAN)
+        } else {
+            (FRAME_WIDTH, FRAME_HEIGHT)
+        };
+        let crop = if settings.crop_overscan { OVERSCAN } else { 0 };
+        let mut width = visible_width * settings.scale;
+        if settings.aspect_correction {
+            width = width * 8 / 7;
+        }
+        let height = visible_height * settings.scale;
+
+        // The NTSC filter gives out more than one image pixel per NES pixel
+        let horizontal_ratio = image.width / FRAME_WIDTH;
+        let vertical_ratio = image.height / FRAME_HEIGHT;
+        let source_columns = (0..width).map(|x| (crop * horizontal_ratio) + x * visible_width * horizontal_ratio / width).collect();
+        let source_rows = (0..height).map(|y| (crop * vertical_ratio) + y * visible_height * vertical_ratio / height).collect();
+
+        let options = if settings.fullscreen {
+            WindowOptions {
+                borderless: true,
+                title: false,
+                resize: false,
+                scale: Scale::FitScreen,
+            }
+        } else {
+            WindowOptions {
+                resize: false,
+                ..WindowOptions::default()
+            }
         };
-        let mut window = Window::new("Output", width as usize, height as usize, options).unwrap_or_else(|e| { panic!("{}", e); });
+        let mut window = Window::new("Output", width, height, options).unwrap_or_else(|e| { panic!("{}", e); });
         return MainWindow {
             window,
-            buffer
+            renderer,
+            image,
+            display: vec![0; width * height],
+            source_columns,
+            source_rows,
+            crop,
+            visible_width,
+            visible_height,
+            sprite_boxes: Vec::new(),
+            pixels: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+            burst_phase: 0,
+            frame_number: 0,
         };
     }
 
     pub fn refresh(&mut self) {
-        self.window.update_with_buffer(self.buffer.as_slice()).unwrap();
+        let frame = Frame {
+            pixels: &self.pixels,
+            burst_phase: self.burst_phase,
+            number: self.frame_number,
+        };
+        self.renderer.render(&frame, &mut self.image);
+
+        let width = self.source_columns.len();
+        for (y, source_y) in self.source_rows.iter().enumerate() {
+            let source_line = &self.image.pixels[(source_y * self.image.width)..((source_y + 1) * self.image.width)];
+            let line = &mut self.display[(y * width)..((y + 1) * width)];
+            for (pixel, source_x) in line.iter_mut().zip(self.source_columns.iter()) {
+                *pixel = source_line[*source_x];
+            }
+        }
+        self.draw_sprite_boxes();
+        self.window.update_with_buffer(self.display.as_slice()).unwrap();
+    }
+
+    pub fn set_sprite_boxes(&mut self, sprite_boxes: &[SpriteBox]) {
+        self.sprite_boxes = sprite_boxes.to_vec();
     }
 
-    fn set_pixel(&mut self, x: i32, y: i32, rgb: (u8, u8, u8)) {
-        let width = 256 as usize;
-        let height = 240 as usize;
-        if x >= 0 && x < 256 {
-            if y >= 0 && y < 240 {
-                self.buffer[(y as usize * width + x as usize)] = pack(rgb.0, rgb.1, rgb.2);
+    fn draw_sprite_boxes(&mut self) {
+        let width = self.source_columns.len();
+        let height = self.source_rows.len();
+        let (crop, visible_width, visible_height) = (self.crop, self.visible_width, self.visible_height);
+        // From frame pixels to display pixels, clamped to the display
+        let to_display_x = |x: usize| (x.saturating_sub(crop) * width / visible_width).min(width - 1);
+        let to_display_y = |y: usize| (y.saturating_sub(crop) * height / visible_height).min(height - 1);
+
+        for sprite in self.sprite_boxes.iter() {
+            let color = if sprite.index == 0 { pack(0xFF, 0x40, 0x40) } else { pack(0x40, 0xFF, 0x40) };
+            let left = to_display_x(sprite.x);
+            let right = to_display_x(sprite.x + 8);
+            let top = to_display_y(sprite.y);
+            let bottom = to_display_y(sprite.y + sprite.height);
+            for x in left..=right {
+                self.display[top * width + x] = color;
+                self.display[bottom * width + x] = color;
+            }
+            for y in top..=bottom {
+                self.display[y * width + left] = color;
+                self.display[y * width + right] = color;
             }
         }
     }
+
+    /// Last frame, uncropped and unscaled
+    pub fn image(&self) -> &Image {
+        return &self.image;
+    }
+
+    pub fn frame_number(&self) -> u64 {
+        return self.frame_number;
+    }
 }
 
-impl PpuOutput for RefCell<MainWindow> {
-    fn set_pixel(&self, x: i32, y: i32, rgb: (u8, u8, u8)) {
-        self.borrow_mut().set_pixel(x, y, rgb);
+impl FrameOutput for RefCell<MainWindow> {
+    fn present_frame(&self, frame: &Frame) {
+        let mut window = self.borrow_mut();
+        window.pixels.copy_from_slice(frame.pixels);
+        window.burst_phase = frame.burst_phase;
+        window.frame_number = frame.number;
     }
-}
\ No newline at end of file
+}
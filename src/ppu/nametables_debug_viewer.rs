@@ -1,15 +1,16 @@
 use minifb::{Window, WindowOptions};
 use crate::bus::Bus;
-use crate::ppu::palette::get_colour_from_palette_ram;
+use crate::ppu::palette::{get_colour_from_palette_ram, Palette};
 use crate::ppu::window_common::pack;
 
 pub struct NametableDebugWindow {
     pub window: Window,
     buffer: Vec<u32>,
+    palette: Palette,
 }
 
 impl NametableDebugWindow {
-    pub fn new() -> Self {
+    pub fn new(palette: Palette) -> Self {
         let width = 256 * 2 as usize;
         let height = 240 * 2 as usize;
 
@@ -22,6 +23,7 @@ impl NametableDebugWindow {
         return Self {
             window,
             buffer,
+            palette,
         };
     }
 
@@ -53,7 +55,7 @@ impl NametableDebugWindow {
                                 let y = nametable_y * 240 + coarse_y * 8 + fine_y;
 
                                 let indexed_color: u8 = ((msb >> fine_x) & 0x1) << 1 | (lsb >> fine_x) & 0x01;
-                                let palette_rgb = get_colour_from_palette_ram(&mut ppu, bus, attrib & 0b11, indexed_color);
+                                let palette_rgb = get_colour_from_palette_ram(&mut ppu, bus, &self.palette, attrib & 0b11, indexed_color);
                                 self.buffer[((y) as usize * (2 * 256) + (x) as usize)] = pack(palette_rgb.0, palette_rgb.1, palette_rgb.2);
                             }
                         }
@@ -0,0 +1,255 @@
+use std::f32::consts::PI;
+use crate::ppu::window_common::pack;
+
+/// Composite output levels of the 2C02 in volts, per luma row of the palette, from the nesdev wiki
+const LEVELS_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const LEVELS_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const BLACK_LEVEL: f32 = 0.312;
+const WHITE_LEVEL: f32 = 1.100;
+/// The emphasized colour phases are pulled down by about a quarter, the RGB palettes use it too
+pub(crate) const EMPHASIS_ATTENUATION: f32 = 0.746;
+/// Where the decoder's colour burst reference sits, in samples, lines colour $x6 up with red
+const BURST_OFFSET: f32 = 4.0;
+
+/// The PPU generates 8 samples per pixel, and one colour subcarrier cycle lasts 12 samples
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+/// Every output pixel covers half of a NES pixel
+const SAMPLES_PER_OUTPUT: usize = 4;
+/// Black samples around the line, so the filter windows never fall off it
+const LINE_PADDING: usize = 32;
+const LINE_SAMPLES: usize = 256 * SAMPLES_PER_PIXEL + 2 * LINE_PADDING;
+
+pub const NTSC_OUTPUT_WIDTH: usize = 256 * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT;
+
+/// How the picture travels to the TV
+#[derive(Copy, Clone, Debug)]
+pub struct NtscSetup {
+    /// Samples averaged into the luma, a full subcarrier cycle (12) removes the dot crawl but blurs
+    pub luma_width: usize,
+    /// Samples the chroma gets demodulated over, should be a multiple of 12
+    pub chroma_width: usize,
+    /// S-video carries luma and chroma on separate wires, there is no crosstalk between them
+    pub separate_luma: bool,
+    /// Without blending, every pixel is decoded on its own and comes out as a clean palette colour
+    pub blend: bool,
+    /// In degrees
+    pub hue: f32,
+    pub saturation: f32,
+}
+
+impl NtscSetup {
+    pub fn composite() -> Self {
+        Self {
+            luma_width: 8,
+            chroma_width: 24,
+            separate_luma: false,
+            blend: true,
+            hue: 0.0,
+            saturation: 1.0,
+        }
+    }
+
+    pub fn svideo() -> Self {
+        Self {
+            luma_width: 4,
+            chroma_width: 24,
+            separate_luma: true,
+            blend: true,
+            hue: 0.0,
+            saturation: 1.0,
+        }
+    }
+
+    pub fn rgb() -> Self {
+        Self {
+            luma_width: 4,
+            chroma_width: 12,
+            separate_luma: true,
+            blend: false,
+            hue: 0.0,
+            saturation: 1.0,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "composite" => Some(Self::composite()),
+            "svideo" | "s-video" => Some(Self::svideo()),
+            "rgb" => Some(Self::rgb()),
+            _ => None,
+        }
+    }
+}
+
+/// Emulates the NTSC signal between the PPU and the TV: the PPU pixels are turned into the
+/// composite waveform the 2C02 generates, which is then decoded back to RGB like a TV would.
+/// Brings the colour fringing, dot crawl and blending some games were drawn around.
+pub struct NtscFilter {
+    setup: NtscSetup,
+    /// Normalized signal level for every 9-bit pixel, at every subcarrier phase
+    signal: Vec<[f32; PHASES]>,
+    /// Normalized luma of every 9-bit pixel, which is the signal averaged over a full cycle
+    luma: Vec<f32>,
+    /// Colour of every pixel decoded on its own, the RGB preset only uses this
+    palette: Vec<u32>,
+    cos_table: [f32; PHASES],
+    sin_table: [f32; PHASES],
+    line_signal: Vec<f32>,
+    line_luma: Vec<f32>,
+}
+
+fn in_color_phase(color: u16, phase: usize) -> bool {
+    return (color as usize + phase) % PHASES < 6;
+}
+
+/// Level of the PPU output for `pixel` at `phase`, as described on the nesdev wiki "NTSC video" page
+fn signal_level(pixel: u16, phase: usize) -> f32 {
+    let color = pixel & 0x0F;
+    let level = if color > 13 { 1 } else { ((pixel >> 4) & 0x03) as usize };
+    let emphasis = pixel >> 6;
+
+    let mut low = LEVELS_LOW[level];
+    let mut high = LEVELS_HIGH[level];
+    if color == 0 {
+        low = high;
+    }
+    if color > 12 {
+        high = low;
+    }
+
+    let mut signal = if in_color_phase(color, phase) { high } else { low };
+    if ((emphasis & 0x01) != 0 && in_color_phase(0, phase))
+        || ((emphasis & 0x02) != 0 && in_color_phase(4, phase))
+        || ((emphasis & 0x04) != 0 && in_color_phase(8, phase)) {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    return (signal - BLACK_LEVEL) / (WHITE_LEVEL - BLACK_LEVEL);
+}
+
+fn burst_angle(phase: usize, hue: f32) -> f32 {
+    return PI * (phase as f32 + BURST_OFFSET) / 6.0 + hue.to_radians();
+}
+
+/// YIQ colour a TV gets out of `pixel` alone, `hue` in degrees
+pub fn decode_pixel(pixel: u16, hue: f32) -> (f32, f32, f32) {
+    let mut y = 0.0;
+    let mut i = 0.0;
+    let mut q = 0.0;
+    for phase in 0..PHASES {
+        let level = signal_level(pixel, phase);
+        y += level;
+        i += level * burst_angle(phase, hue).cos();
+        q += level * burst_angle(phase, hue).sin();
+    }
+    return (y / PHASES as f32, i * 2.0 / PHASES as f32, q * 2.0 / PHASES as f32);
+}
+
+/// FCC matrix, colour channels come out between 0 and 1 for most colours
+pub fn yiq_to_rgb(y: f32, i: f32, q: f32) -> (f32, f32, f32) {
+    let r = y + 0.946882 * i + 0.623557 * q;
+    let g = y - 0.274788 * i - 0.635691 * q;
+    let b = y - 1.108545 * i + 1.709007 * q;
+    return (r, g, b);
+}
+
+fn yiq_to_packed_rgb(y: f32, i: f32, q: f32) -> u32 {
+    let (r, g, b) = yiq_to_rgb(y, i, q);
+    let to_u8 = |v: f32| (v * 255.0).max(0.0).min(255.0) as u8;
+    return pack(to_u8(r), to_u8(g), to_u8(b));
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> Self {
+        let mut cos_table = [0.0; PHASES];
+        let mut sin_table = [0.0; PHASES];
+        for phase in 0..PHASES {
+            cos_table[phase] = burst_angle(phase, setup.hue).cos();
+            sin_table[phase] = burst_angle(phase, setup.hue).sin();
+        }
+
+        let mut signal = Vec::with_capacity(512);
+        let mut luma = Vec::with_capacity(512);
+        let mut palette = Vec::with_capacity(512);
+        for pixel in 0..512u16 {
+            let mut levels = [0.0; PHASES];
+            for phase in 0..PHASES {
+                levels[phase] = signal_level(pixel, phase);
+            }
+            let (y, i, q) = decode_pixel(pixel, setup.hue);
+            palette.push(yiq_to_packed_rgb(y, i * setup.saturation, q * setup.saturation));
+            signal.push(levels);
+            luma.push(y);
+        }
+
+        Self {
+            setup,
+            signal,
+            luma,
+            palette,
+            cos_table,
+            sin_table,
+            line_signal: vec![0.0; LINE_SAMPLES],
+            line_luma: vec![0.0; LINE_SAMPLES],
+        }
+    }
+
+    /// Filters a 256 pixels wide frame into `output`, `NTSC_OUTPUT_WIDTH` pixels wide.
+    /// `burst_phase` is the subcarrier phase at the first pixel, every line starts 4 samples further.
+    pub fn filter_frame(&mut self, pixels: &[u16], burst_phase: u8, output: &mut [u32]) {
+        let lines = pixels.len() / 256;
+        for y in 0..lines {
+            let line = &pixels[(y * 256)..((y + 1) * 256)];
+            let line_output = &mut output[(y * NTSC_OUTPUT_WIDTH)..((y + 1) * NTSC_OUTPUT_WIDTH)];
+            if self.setup.blend {
+                // A line is 341 dots of 8 samples long, 4 samples over a whole number of cycles
+                let line_phase = (burst_phase as usize + y * 4) % PHASES;
+                self.filter_line(line, line_phase, line_output);
+            } else {
+                for x in 0..NTSC_OUTPUT_WIDTH {
+                    line_output[x] = self.palette[(line[x / 2] & 0x1FF) as usize];
+                }
+            }
+        }
+    }
+
+    fn filter_line(&mut self, line: &[u16], line_phase: usize, output: &mut [u32]) {
+        // Sample index i sits at phase (line_phase + i - LINE_PADDING), this keeps the modulo positive
+        let phase_offset = line_phase + PHASES - LINE_PADDING % PHASES;
+
+        for x in 0..256 {
+            let pixel = (line[x] & 0x1FF) as usize;
+            for sample in 0..SAMPLES_PER_PIXEL {
+                let index = LINE_PADDING + x * SAMPLES_PER_PIXEL + sample;
+                self.line_signal[index] = self.signal[pixel][(index + phase_offset) % PHASES];
+                self.line_luma[index] = self.luma[pixel];
+            }
+        }
+
+        let luma_width = self.setup.luma_width;
+        let chroma_width = self.setup.chroma_width;
+        let chroma_scale = 2.0 * self.setup.saturation / chroma_width as f32;
+        for x in 0..NTSC_OUTPUT_WIDTH {
+            let center = LINE_PADDING + x * SAMPLES_PER_OUTPUT + SAMPLES_PER_OUTPUT / 2;
+
+            let luma_source = if self.setup.separate_luma { &self.line_luma } else { &self.line_signal };
+            let luma_start = center - luma_width / 2;
+            let y = luma_source[luma_start..(luma_start + luma_width)].iter().sum::<f32>() / luma_width as f32;
+
+            let mut i = 0.0;
+            let mut q = 0.0;
+            let chroma_start = center - chroma_width / 2;
+            for index in chroma_start..(chroma_start + chroma_width) {
+                let mut chroma = self.line_signal[index];
+                if self.setup.separate_luma {
+                    chroma -= self.line_luma[index];
+                }
+                let phase = (index + phase_offset) % PHASES;
+                i += chroma * self.cos_table[phase];
+                q += chroma * self.sin_table[phase];
+            }
+
+            output[x] = yiq_to_packed_rgb(y, i * chroma_scale, q * chroma_scale);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use minifb::{Window, WindowOptions};
+use crate::bus::Bus;
+use crate::ppu::palette::{get_colour_from_palette_ram, Palette};
+use crate::ppu::window_common::{pack, draw_text};
+
+const COLUMNS: usize = 8;
+const ROWS: usize = 8;
+const SPRITE_SCALE: usize = 3;
+const CELL_WIDTH: usize = 120;
+const CELL_HEIGHT: usize = 56;
+const TEXT_X: usize = 8 * SPRITE_SCALE + 8;
+
+/// Where a sprite sits on screen, for the bounding boxes on the main window
+#[derive(Copy, Clone, Debug)]
+pub struct SpriteBox {
+    pub index: u8,
+    pub x: usize,
+    pub y: usize,
+    pub height: usize,
+}
+
+/// The 64 OAM entries, each drawn in its palette along its position, tile and attributes
+pub struct OamDebugWindow {
+    pub window: Window,
+    buffer: Vec<u32>,
+    palette: Palette,
+    sprite_boxes: Vec<SpriteBox>,
+}
+
+impl OamDebugWindow {
+    pub fn new(palette: Palette) -> Self {
+        let width = COLUMNS * CELL_WIDTH;
+        let height = ROWS * CELL_HEIGHT;
+
+        let buffer: Vec<u32> = vec![0; (width * height) as usize];
+        let options = WindowOptions {
+            resize: false,
+            ..WindowOptions::default()
+        };
+        let window = Window::new("OAM", width as usize, height as usize, options).unwrap_or_else(|e| { panic!("{}", e); });
+        return Self {
+            window,
+            buffer,
+            palette,
+            sprite_boxes: Vec::new(),
+        };
+    }
+
+    /// Visible sprites as of the last update
+    pub fn sprite_boxes(&self) -> &[SpriteBox] {
+        return &self.sprite_boxes;
+    }
+
+    pub fn update(&mut self, bus: &Bus) {
+        let width = COLUMNS * CELL_WIDTH;
+        let mut ppu = bus.ppu.borrow_mut();
+        let tall_sprites = ppu.control.sprite_size() != 0;
+        let sprite_height = if tall_sprites { 16 } else { 8 };
+
+        for pixel in self.buffer.iter_mut() {
+            *pixel = pack(0x10, 0x10, 0x10);
+        }
+        self.sprite_boxes.clear();
+
+        for index in 0..64 {
+            let sprite = ppu.oam[index];
+            let cell_x = (index % COLUMNS) * CELL_WIDTH;
+            let cell_y = (index / COLUMNS) * CELL_HEIGHT;
+            let attribute = sprite.attribute() as u8;
+            let sprite_palette = attribute & 0x03;
+            let behind_background = attribute & 0x20 != 0;
+            let flip_horizontal = attribute & 0x40 != 0;
+            let flip_vertical = attribute & 0x80 != 0;
+
+            // Transparent pixels show as a checkered background
+            for y in 0..(sprite_height * SPRITE_SCALE) {
+                for x in 0..(8 * SPRITE_SCALE) {
+                    let dark = ((x / SPRITE_SCALE) + (y / SPRITE_SCALE)) % 2 == 0;
+                    self.buffer[(cell_y + 4 + y) * width + cell_x + 4 + x] = if dark { pack(0x28, 0x28, 0x28) } else { pack(0x38, 0x38, 0x38) };
+                }
+            }
+
+            for row in 0..sprite_height {
+                let pattern_row = if flip_vertical { sprite_height - 1 - row } else { row };
+                // 8x16 sprites pick their pattern table with bit 0 of the tile number
+                let tile_address = if tall_sprites {
+                    let tile = (sprite.id() as u16 & 0xFE) + (pattern_row as u16 / 8);
+                    ((sprite.id() as u16 & 0x01) << 12) | (tile << 4)
+                } else {
+                    ((ppu.control.pattern_sprite() as u16) << 12) | ((sprite.id() as u16) << 4)
+                };
+                let address = tile_address | (pattern_row as u16 & 0x07);
+                let lsb = ppu.ppu_read(bus, address, true);
+                let msb = ppu.ppu_read(bus, address + 8, true);
+
+                for column in 0..8 {
+                    let bit = if flip_horizontal { column } else { 7 - column };
+                    let pixel = ((msb >> bit) & 0x01) << 1 | (lsb >> bit) & 0x01;
+                    if pixel == 0 {
+                        continue;
+                    }
+                    let rgb = get_colour_from_palette_ram(&mut ppu, bus, &self.palette, 4 + sprite_palette, pixel);
+                    for dy in 0..SPRITE_SCALE {
+                        for dx in 0..SPRITE_SCALE {
+                            let x = cell_x + 4 + column * SPRITE_SCALE + dx;
+                            let y = cell_y + 4 + row * SPRITE_SCALE + dy;
+                            self.buffer[y * width + x] = pack(rgb.0, rgb.1, rgb.2);
+                        }
+                    }
+                }
+            }
+
+            // Sprites are drawn one line below their Y, and anything past 239 is hidden
+            let hidden = sprite.y() >= 0xEF;
+            let text_color = if hidden { pack(0x80, 0x80, 0x80) } else { pack(0xFF, 0xFF, 0xFF) };
+            let lines = [
+                format!("#{}", index),
+                format!("X {} Y {}", sprite.x(), sprite.y()),
+                format!("T ${:02X} P {}", sprite.id(), sprite_palette),
+                format!("{} {}{}", if behind_background { "BACK" } else { "FRONT" }, if flip_horizontal { "H" } else { "-" }, if flip_vertical { "V" } else { "-" }),
+            ];
+            for (i, line) in lines.iter().enumerate() {
+                draw_text(&mut self.buffer, width, cell_x + TEXT_X, cell_y + 6 + i * 9, line, text_color, 1);
+            }
+
+            if !hidden {
+                self.sprite_boxes.push(SpriteBox {
+                    index: index as u8,
+                    x: sprite.x() as usize,
+                    y: sprite.y() as usize + 1,
+                    height: sprite_height,
+                });
+            }
+        }
+
+        self.window.update_with_buffer(self.buffer.as_slice()).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
 use crate::ppu::Ppu;
 use crate::bus::Bus;
+use crate::ppu::ntsc_filter::{decode_pixel, yiq_to_rgb, EMPHASIS_ATTENUATION};
+use std::fs::File;
+use std::io::{Read, Write};
 
 type RGB = (u8, u8, u8);
 
@@ -70,7 +73,118 @@ const NES_PALETTE_RGB: [(u8, u8, u8); 64] = [
     (0, 0, 0),
 ];
 
-pub fn get_colour_from_palette_ram(ppu: &mut Ppu, bus: &Bus, palette: u8, pixel: u8) -> (u8, u8, u8) {
-    let color_palette_index = ppu.ppu_read(bus, (0x3F00 + ((palette as u16) << 2) + pixel as u16) as u16, false) & 0x3F;
-    return NES_PALETTE_RGB[color_palette_index as usize];
-}
\ No newline at end of file
+/// Colours of the 512 possible PPU output pixels: colour index in bits 0-5, emphasis bits in 6-8
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<RGB>,
+}
+
+/// Knobs for generating a palette out of the NTSC signal levels, the defaults leave the decoded colours as they are
+#[derive(Copy, Clone, Debug)]
+pub struct PaletteParameters {
+    /// In degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl PaletteParameters {
+    pub fn new() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Palette {
+    /// The built-in palette
+    pub fn new() -> Self {
+        return Self::from_base_colors(&NES_PALETTE_RGB);
+    }
+
+    /// Emphasized colours get derived from the 64 base ones
+    fn from_base_colors(base: &[RGB]) -> Self {
+        let mut colors = Vec::with_capacity(512);
+        for pixel in 0..512u16 {
+            let (r, g, b) = base[(pixel & 0x3F) as usize];
+            let emphasis = (pixel >> 6) & 0x07;
+            // The blacks of columns $E and $F are left alone
+            if emphasis == 0 || (pixel & 0x0F) >= 0x0E {
+                colors.push((r, g, b));
+                continue;
+            }
+
+            // Emphasizing a colour attenuates the two others
+            let attenuate = |value: u8, dimmed: bool| {
+                if dimmed { (value as f32 * EMPHASIS_ATTENUATION) as u8 } else { value }
+            };
+            let red = emphasis & 0x01 != 0;
+            let green = emphasis & 0x02 != 0;
+            let blue = emphasis & 0x04 != 0;
+            colors.push((attenuate(r, green || blue), attenuate(g, red || blue), attenuate(b, red || green)));
+        }
+        return Self {
+            colors,
+        };
+    }
+
+    /// Loads a `.pal` file, either the 64 base colours (192 bytes) or all 512 of them (1536 bytes)
+    pub fn load(filename: &str) -> Self {
+        let mut f = File::open(filename).expect("File not found");
+        let mut contents = Vec::new();
+        f.read_to_end(&mut contents).expect("failed to read file");
+
+        let colors: Vec<RGB> = contents.chunks(3).filter(|c| c.len() == 3).map(|c| (c[0], c[1], c[2])).collect();
+        match contents.len() {
+            192 => Self::from_base_colors(&colors),
+            1536 => Self { colors },
+            _ => panic!("palette files should be 192 or 1536 bytes long"),
+        }
+    }
+
+    /// Writes all 512 colours, in the same format `load` reads
+    pub fn save(&self, filename: &str) {
+        let mut f = File::create(filename).expect("failed to create palette file");
+        let contents: Vec<u8> = self.colors.iter().flat_map(|c| vec![c.0, c.1, c.2]).collect();
+        f.write_all(&contents).expect("failed to write palette file");
+    }
+
+    /// Decodes every pixel the way a TV would, then applies the picture settings
+    pub fn generate(parameters: &PaletteParameters) -> Self {
+        let mut colors = Vec::with_capacity(512);
+        for pixel in 0..512u16 {
+            let (y, i, q) = decode_pixel(pixel, parameters.hue);
+            let y = y * parameters.contrast + parameters.brightness;
+            let (r, g, b) = yiq_to_rgb(y, i * parameters.saturation, q * parameters.saturation);
+            let to_u8 = |v: f32| (v.max(0.0).min(1.0).powf(1.0 / parameters.gamma) * 255.0) as u8;
+            colors.push((to_u8(r), to_u8(g), to_u8(b)));
+        }
+        return Self {
+            colors,
+        };
+    }
+
+    pub fn rgb(&self, pixel: u16) -> RGB {
+        return self.colors[(pixel & 0x1FF) as usize];
+    }
+}
+
+/// 6-bit colour index stored in palette RAM for that palette/pixel pair.
+/// Greyscale mode keeps only the grey column of the palette.
+pub fn get_pixel_from_palette_ram(ppu: &mut Ppu, bus: &Bus, palette: u8, pixel: u8) -> u8 {
+    let mut color = ppu.ppu_read(bus, (0x3F00 + ((palette as u16) << 2) + pixel as u16) as u16, false) & 0x3F;
+    if ppu.mask.grayscale() != 0 {
+        color &= 0x30;
+    }
+    return color;
+}
+
+pub fn get_colour_from_palette_ram(ppu: &mut Ppu, bus: &Bus, colors: &Palette, palette: u8, pixel: u8) -> (u8, u8, u8) {
+    return colors.rgb(get_pixel_from_palette_ram(ppu, bus, palette, pixel) as u16);
+}
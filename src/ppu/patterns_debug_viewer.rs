@@ -1,15 +1,16 @@
 use minifb::{WindowOptions, Window};
 use crate::bus::Bus;
-use crate::ppu::palette::get_colour_from_palette_ram;
+use crate::ppu::palette::{get_colour_from_palette_ram, Palette};
 use crate::ppu::window_common::*;
 
 pub struct PatternsDebugWindow {
     pub window: Window,
     buffer: Vec<u32>,
+    palette: Palette,
 }
 
 impl PatternsDebugWindow {
-    pub fn new() -> Self {
+    pub fn new(palette: Palette) -> Self {
         let width = 256 as usize;
         let height = 128 as usize;
 
@@ -21,7 +22,8 @@ impl PatternsDebugWindow {
         let mut window = Window::new("Pattern tables", width as usize, height as usize, options).unwrap_or_else(|e| { panic!("{}", e); });
         return Self {
             window,
-            buffer
+            buffer,
+            palette,
         };
     }
 
@@ -55,7 +57,7 @@ impl PatternsDebugWindow {
                 let fine_x = 7 - (x as u8 & 7);
 
                 let indexed_color: u8 = ((msb >> fine_x) & 0x1) << 1 | (lsb >> fine_x) & 0x01;
-                let palette_rgb = get_colour_from_palette_ram(&mut bus.ppu.borrow_mut(), bus, 0, indexed_color);
+                let palette_rgb = get_colour_from_palette_ram(&mut bus.ppu.borrow_mut(), bus, &self.palette, 0, indexed_color);
                 self.buffer[(y as usize * width + x as usize)] = pack(palette_rgb.0, palette_rgb.1, palette_rgb.2);
                 //TODO use real palettes
                 /*let color = match (indexed_color) {
@@ -16,4 +16,78 @@ pub fn pack(r: u8, g: u8, b: u8) -> u32 {
 
 fn clamp<T: PartialOrd>(v: T, min: T, max: T) -> T {
     if v < min { min } else if v > max { max } else { v }
-}
\ No newline at end of file
+}
+
+/// Draws text with a tiny 3x5 font, each font pixel being `scale` pixels wide.
+/// Only upper case letters, digits and a few symbols are available.
+pub fn draw_text(buffer: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32, scale: usize) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph = glyph(c.to_ascii_uppercase());
+        let glyph_x = x + i * 4 * scale;
+        for row in 0..5 {
+            for column in 0..3 {
+                if (glyph >> (14 - (row * 3 + column))) & 0x01 == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_x + column * scale + dx;
+                        let index = (y + row * scale + dy) * width + px;
+                        if px < width && index < buffer.len() {
+                            buffer[index] = color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Rows top to bottom, 3 bits each
+fn glyph(c: char) -> u16 {
+    match c {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b011_100_100_100_011,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b011_100_101_101_011,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_010,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010,
+        'P' => 0b110_101_110_100_100,
+        'Q' => 0b010_101_101_110_011,
+        'R' => 0b110_101_110_101_101,
+        'S' => 0b011_100_010_001_110,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        '#' => 0b101_111_101_111_101,
+        '.' => 0b000_000_000_000_010,
+        ':' => 0b000_010_000_010_000,
+        '-' => 0b000_000_111_000_000,
+        '/' => 0b001_001_010_100_100,
+        '$' => 0b011_110_010_011_110,
+        _ => 0,
+    }
+}
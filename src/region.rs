@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+/// Console timing flavour. Dendy is the Russian famiclone: PAL video timing with a CPU
+/// clocked closer to the NTSC one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn master_clock_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0,
+            Region::Pal | Region::Dendy => 26_601_712.5,
+        }
+    }
+
+    /// Master clock ticks per CPU cycle
+    pub fn cpu_clock_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clock ticks per PPU dot
+    pub fn ppu_clock_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock_rate() / self.cpu_clock_divider() as f64
+    }
+
+    /// Including the pre-render line
+    pub fn scanlines(&self) -> i16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_scanline(&self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            // Dendy keeps the NTSC vblank length and pads the post-render area instead
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Only the NTSC PPU shortens odd frames by a dot when rendering
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let mut dots_per_frame = 341.0 * self.scanlines() as f64;
+        if self.skips_odd_frame_dot() {
+            dots_per_frame -= 0.5;
+        }
+        self.master_clock_rate() / self.ppu_clock_divider() as f64 / dots_per_frame
+    }
+
+    /// Exact `frame_rate`, as a numerator and a denominator
+    pub fn frame_rate_fraction(&self) -> (u32, u32) {
+        match self {
+            Region::Ntsc => (39_375_000, 655_171),
+            Region::Pal | Region::Dendy => (322_445, 6_448),
+        }
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate())
+    }
+
+    /// APU frame counter quarter frame period, in twelfths of a CPU cycle
+    pub fn apu_frame_period(&self) -> u32 {
+        match self {
+            Region::Ntsc | Region::Dendy => 89490,
+            Region::Pal => 99756,
+        }
+    }
+
+    pub fn noise_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+            Region::Pal => &PAL_NOISE_PERIODS,
+        }
+    }
+
+    /// In CPU cycles
+    pub fn dmc_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_PERIODS,
+            Region::Pal => &PAL_DMC_PERIODS,
+        }
+    }
+}
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [0x04, 0x08, 0x10, 0x20, 0x40, 0x60, 0x80, 0xA0, 0xCA, 0xFE, 0x17C, 0x1FC, 0x2FA, 0x3F8, 0x7F2, 0xFE4];
+const PAL_NOISE_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+const NTSC_DMC_PERIODS: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_DMC_PERIODS: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
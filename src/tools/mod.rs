@@ -1,3 +1,7 @@
 mod dump_memory;
+pub mod wav_writer;
+pub mod png_writer;
+pub mod y4m_writer;
+pub mod video_recorder;
 
-pub use dump_memory::*;
\ No newline at end of file
+pub use dump_memory::*;
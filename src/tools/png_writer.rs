@@ -0,0 +1,92 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+
+/// Stored deflate blocks can't hold more than that
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for n in 0..256 {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        table[n] = c;
+    }
+    return table;
+}
+
+fn crc32(table: &[u32; 256], data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc = table[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    return crc ^ 0xFFFFFFFF;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+/// zlib stream made of stored (uncompressed) deflate blocks, every decoder handles those
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(if last { 0x01 } else { 0x00 });
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    return stream;
+}
+
+fn write_chunk(writer: &mut impl Write, table: &[u32; 256], kind: &[u8; 4], data: &[u8]) {
+    let mut crc_data = kind.to_vec();
+    crc_data.extend_from_slice(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes()).unwrap();
+    writer.write_all(&crc_data).unwrap();
+    writer.write_all(&crc32(table, &crc_data).to_be_bytes()).unwrap();
+}
+
+/// Saves 0RGB packed pixels (what the windows display) as an uncompressed 8-bit RGB PNG
+pub fn write_png(filename: &str, width: usize, height: usize, pixels: &[u32]) {
+    let f = OpenOptions::new().write(true).create(true).truncate(true).open(filename).expect("failed to open file");
+    let mut writer = BufWriter::new(f);
+    let table = crc32_table();
+
+    writer.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, no filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut writer, &table, b"IHDR", &header);
+
+    // Every scanline starts with its filter type, always 0 (none) here
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for y in 0..height {
+        raw.push(0);
+        for pixel in &pixels[(y * width)..((y + 1) * width)] {
+            raw.push((pixel >> 16) as u8);
+            raw.push((pixel >> 8) as u8);
+            raw.push(*pixel as u8);
+        }
+    }
+    write_chunk(&mut writer, &table, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut writer, &table, b"IEND", &[]);
+    writer.flush().unwrap();
+}
@@ -0,0 +1,36 @@
+use crate::bus::Bus;
+use crate::ppu::frame_renderer::{FrameRenderer, Image};
+use crate::tools::y4m_writer::Y4mWriter;
+use crate::tools::wav_writer::WavSampleFormat;
+
+/// Records `<name>.y4m` along `<name>.wav`. Both follow the emulated frames rather than
+/// the wall clock, so they stay in sync however fast the emulation runs.
+pub struct VideoRecorder {
+    writer: Y4mWriter,
+    renderer: FrameRenderer,
+    image: Image,
+}
+
+impl VideoRecorder {
+    pub fn start(nes: &Bus, name: &str, renderer: FrameRenderer, sample_rate: u32, format: WavSampleFormat) -> Self {
+        let image = renderer.create_image();
+        let writer = Y4mWriter::create(&format!("{}.y4m", name), image.width, image.height, nes.region.frame_rate_fraction());
+        nes.apu.borrow_mut().start_video_audio(&format!("{}.wav", name), sample_rate, format);
+        return Self {
+            writer,
+            renderer,
+            image,
+        };
+    }
+
+    /// To be called once after every emulated frame
+    pub fn record_frame(&mut self, nes: &Bus) {
+        self.renderer.render(&nes.ppu.borrow().frame(), &mut self.image);
+        self.writer.write_frame(&self.image.pixels);
+    }
+
+    pub fn stop(mut self, nes: &Bus) {
+        self.writer.finish();
+        nes.apu.borrow_mut().stop_video_audio();
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write, Seek, SeekFrom};
+use crate::apu::audio_sink::StereoSample;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WavSampleFormat {
+    Int8,
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavSampleFormat {
+    pub fn from_bit_depth(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(WavSampleFormat::Int8),
+            16 => Some(WavSampleFormat::Int16),
+            24 => Some(WavSampleFormat::Int24),
+            32 => Some(WavSampleFormat::Float32),
+            _ => None,
+        }
+    }
+
+    fn bytes_per_sample(&self) -> u16 {
+        match self {
+            WavSampleFormat::Int8 => 1,
+            WavSampleFormat::Int16 => 2,
+            WavSampleFormat::Int24 => 3,
+            WavSampleFormat::Float32 => 4,
+        }
+    }
+}
+
+/// Minimal RIFF/WAVE writer. The chunk sizes are patched in once we're done writing,
+/// either by calling `finish` or when the writer is dropped.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    format: WavSampleFormat,
+    channels: u16,
+    data_bytes: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(filename: &str, sample_rate: u32, channels: u16, format: WavSampleFormat) -> Self {
+        let f = OpenOptions::new().write(true).create(true).truncate(true).open(filename).expect("failed to open file");
+        let mut writer = BufWriter::new(f);
+
+        let block_align = channels * format.bytes_per_sample();
+        let format_tag: u16 = if format == WavSampleFormat::Float32 { 3 } else { 1 };
+
+        writer.write_all(b"RIFF").unwrap();
+        writer.write_all(&0u32.to_le_bytes()).unwrap(); // patched in finish()
+        writer.write_all(b"WAVE").unwrap();
+
+        writer.write_all(b"fmt ").unwrap();
+        writer.write_all(&16u32.to_le_bytes()).unwrap();
+        writer.write_all(&format_tag.to_le_bytes()).unwrap();
+        writer.write_all(&channels.to_le_bytes()).unwrap();
+        writer.write_all(&sample_rate.to_le_bytes()).unwrap();
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes()).unwrap();
+        writer.write_all(&block_align.to_le_bytes()).unwrap();
+        writer.write_all(&(format.bytes_per_sample() * 8).to_le_bytes()).unwrap();
+
+        writer.write_all(b"data").unwrap();
+        writer.write_all(&0u32.to_le_bytes()).unwrap(); // patched in finish()
+
+        return WavWriter {
+            writer,
+            format,
+            channels,
+            data_bytes: 0,
+            finished: false,
+        };
+    }
+
+    /// Writes one sample for one channel, expected in the -1..1 range
+    pub fn write_sample(&mut self, sample: f32) {
+        let sample = if sample > 1.0 { 1.0 } else if sample < -1.0 { -1.0 } else { sample };
+        match self.format {
+            WavSampleFormat::Int8 => {
+                // 8-bit wav is unsigned, silence is 128
+                let value = (sample * 127.0 + 128.0).min(255.0) as u8;
+                self.writer.write_all(&[value]).unwrap();
+            }
+            WavSampleFormat::Int16 => {
+                let value = (sample * std::i16::MAX as f32) as i16;
+                self.writer.write_all(&value.to_le_bytes()).unwrap();
+            }
+            WavSampleFormat::Int24 => {
+                let value = (sample * 8_388_607.0) as i32;
+                self.writer.write_all(&value.to_le_bytes()[0..3]).unwrap();
+            }
+            WavSampleFormat::Float32 => {
+                self.writer.write_all(&sample.to_le_bytes()).unwrap();
+            }
+        }
+        self.data_bytes += self.format.bytes_per_sample() as u32;
+    }
+
+    pub fn write_stereo_samples(&mut self, samples: &[StereoSample]) {
+        for sample in samples {
+            match self.channels {
+                1 => self.write_sample((sample[0] + sample[1]) * 0.5),
+                _ => {
+                    self.write_sample(sample[0]);
+                    self.write_sample(sample[1]);
+                }
+            }
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        // RIFF chunks are padded to an even size
+        if self.data_bytes % 2 == 1 {
+            self.writer.write_all(&[0]).unwrap();
+        }
+
+        let riff_size = 4 + (8 + 16) + (8 + self.data_bytes + self.data_bytes % 2);
+        self.writer.seek(SeekFrom::Start(4)).unwrap();
+        self.writer.write_all(&riff_size.to_le_bytes()).unwrap();
+        self.writer.seek(SeekFrom::Start(40)).unwrap();
+        self.writer.write_all(&self.data_bytes.to_le_bytes()).unwrap();
+        self.writer.flush().unwrap();
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::fs::File;
+
+/// Uncompressed YUV4MPEG2 video, 4:4:4 so the chroma of single pixels doesn't get smeared.
+/// Most video tools take it as is, ie `ffmpeg -i video.y4m -i video.wav out.mkv`.
+pub struct Y4mWriter {
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+    planes: Vec<u8>,
+}
+
+impl Y4mWriter {
+    /// `frame_rate` is a numerator and a denominator
+    pub fn create(filename: &str, width: usize, height: usize, frame_rate: (u32, u32)) -> Self {
+        let f = OpenOptions::new().write(true).create(true).truncate(true).open(filename).expect("failed to open file");
+        let mut writer = BufWriter::new(f);
+        write!(writer, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444\n", width, height, frame_rate.0, frame_rate.1).unwrap();
+
+        return Y4mWriter {
+            writer,
+            width,
+            height,
+            planes: vec![0; width * height * 3],
+        };
+    }
+
+    /// Takes packed 0RGB pixels, converted to BT.601 studio range
+    pub fn write_frame(&mut self, pixels: &[u32]) {
+        let plane_size = self.width * self.height;
+        for (index, pixel) in pixels.iter().take(plane_size).enumerate() {
+            let r = ((pixel >> 16) & 0xFF) as i32;
+            let g = ((pixel >> 8) & 0xFF) as i32;
+            let b = (pixel & 0xFF) as i32;
+            self.planes[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            self.planes[plane_size + index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            self.planes[2 * plane_size + index] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.writer.write_all(b"FRAME\n").unwrap();
+        self.writer.write_all(&self.planes).unwrap();
+    }
+
+    pub fn finish(&mut self) {
+        self.writer.flush().unwrap();
+    }
+}
+
+impl Drop for Y4mWriter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}